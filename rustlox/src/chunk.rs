@@ -3,6 +3,7 @@ use std::convert::TryFrom;
 use std::convert::TryInto;
 use std::result::Result;
 
+#[derive(Copy, Clone)]
 #[repr(u8)]
 pub enum Op {
     Constant,
@@ -14,6 +15,8 @@ pub enum Op {
     SetLocal,
     GetGlobal,
     DefineGlobal,
+    /// Like `DefineGlobal`, but later `SetGlobal`s on the name fail.
+    DefineConstant,
     SetGlobal,
     GetUpvalue,
     SetUpvalue,
@@ -24,15 +27,55 @@ pub enum Op {
     Subtract,
     Multiply,
     Divide,
+    Modulo,
+    Power,
+    IntDivide,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
     Not,
     Negate,
     Print,
+    BuildString,
     Jump,
     JumpIfFalse,
+    JumpIfNotNil,
+    /// Followed by a constant holding the lowest case, a u16 count of cases,
+    /// one u16 offset per case and one for the default. Offsets are relative
+    /// to the end of the table.
+    JumpTable,
+    /// Pops the end and start of a range and pushes whether the value below
+    /// them lies inside it. The operand byte is 1 when the end is included.
+    InRange,
+    /// Followed by the slot of a for-in loop's iterable and a u16 jump taken
+    /// when the iterable is exhausted. The next index lives in the slot after
+    /// the iterable and the loop variable in the one after that.
+    IterNext,
+    /// Ends the loop when an iterator function has returned nil. Operands are
+    /// the same as `IterNext`'s. Nil is the only end marker, so an iterator
+    /// function can't yield nil as a value.
+    IterCheck,
     Loop,
     Call,
+    /// Like `Call`, but reuses the current frame when calling a closure. Only
+    /// emitted where nothing but a `Return` runs after the call.
+    TailCall,
+    /// Pops the operand's number of values and pushes a list of them.
+    Collect,
+    /// Pops a list and appends its elements to the list beneath it.
+    Extend,
+    /// Pops a list of arguments and calls the value beneath it with them.
+    CallSpread,
+    /// `CallSpread` in tail position.
+    TailCallSpread,
     Closure,
     CloseUpvalue,
+    /// Pops a value and hands it to whoever resumed the running fiber. The
+    /// value the fiber is next resumed with takes its place.
+    Yield,
     Return,
 }
 
@@ -50,6 +93,7 @@ impl TryFrom<u8> for Op {
             x if x == Op::SetLocal as u8 => Ok(Op::SetLocal),
             x if x == Op::GetGlobal as u8 => Ok(Op::GetGlobal),
             x if x == Op::DefineGlobal as u8 => Ok(Op::DefineGlobal),
+            x if x == Op::DefineConstant as u8 => Ok(Op::DefineConstant),
             x if x == Op::SetGlobal as u8 => Ok(Op::SetGlobal),
             x if x == Op::GetUpvalue as u8 => Ok(Op::GetUpvalue),
             x if x == Op::SetUpvalue as u8 => Ok(Op::SetUpvalue),
@@ -60,15 +104,36 @@ impl TryFrom<u8> for Op {
             x if x == Op::Subtract as u8 => Ok(Op::Subtract),
             x if x == Op::Multiply as u8 => Ok(Op::Multiply),
             x if x == Op::Divide as u8 => Ok(Op::Divide),
+            x if x == Op::Modulo as u8 => Ok(Op::Modulo),
+            x if x == Op::Power as u8 => Ok(Op::Power),
+            x if x == Op::IntDivide as u8 => Ok(Op::IntDivide),
+            x if x == Op::BitAnd as u8 => Ok(Op::BitAnd),
+            x if x == Op::BitOr as u8 => Ok(Op::BitOr),
+            x if x == Op::BitXor as u8 => Ok(Op::BitXor),
+            x if x == Op::BitNot as u8 => Ok(Op::BitNot),
+            x if x == Op::ShiftLeft as u8 => Ok(Op::ShiftLeft),
+            x if x == Op::ShiftRight as u8 => Ok(Op::ShiftRight),
             x if x == Op::Not as u8 => Ok(Op::Not),
             x if x == Op::Negate as u8 => Ok(Op::Negate),
             x if x == Op::Print as u8 => Ok(Op::Print),
+            x if x == Op::BuildString as u8 => Ok(Op::BuildString),
             x if x == Op::Jump as u8 => Ok(Op::Jump),
             x if x == Op::JumpIfFalse as u8 => Ok(Op::JumpIfFalse),
+            x if x == Op::JumpIfNotNil as u8 => Ok(Op::JumpIfNotNil),
+            x if x == Op::JumpTable as u8 => Ok(Op::JumpTable),
+            x if x == Op::InRange as u8 => Ok(Op::InRange),
+            x if x == Op::IterNext as u8 => Ok(Op::IterNext),
+            x if x == Op::IterCheck as u8 => Ok(Op::IterCheck),
             x if x == Op::Loop as u8 => Ok(Op::Loop),
             x if x == Op::Call as u8 => Ok(Op::Call),
+            x if x == Op::TailCall as u8 => Ok(Op::TailCall),
+            x if x == Op::Collect as u8 => Ok(Op::Collect),
+            x if x == Op::Extend as u8 => Ok(Op::Extend),
+            x if x == Op::CallSpread as u8 => Ok(Op::CallSpread),
+            x if x == Op::TailCallSpread as u8 => Ok(Op::TailCallSpread),
             x if x == Op::Closure as u8 => Ok(Op::Closure),
             x if x == Op::CloseUpvalue as u8 => Ok(Op::CloseUpvalue),
+            x if x == Op::Yield as u8 => Ok(Op::Yield),
             x if x == Op::Return as u8 => Ok(Op::Return),
             _ => {
                 if v < Op::Return as u8 {
@@ -146,6 +211,7 @@ impl Chunk {
             Ok(Op::SetLocal) => self.byte_instruction("OP_SET_LOCAL", offset),
             Ok(Op::GetGlobal) => self.constant_instruction("OP_GET_GLOBAL", offset),
             Ok(Op::DefineGlobal) => self.constant_instruction("OP_DEFINE_GLOBAL", offset),
+            Ok(Op::DefineConstant) => self.constant_instruction("OP_DEFINE_CONSTANT", offset),
             Ok(Op::SetGlobal) => self.constant_instruction("OP_SET_GLOBAL", offset),
             Ok(Op::GetUpvalue) => self.byte_instruction("OP_GET_UPVALUE", offset),
             Ok(Op::SetUpvalue) => self.byte_instruction("OP_SET_UPVALUE", offset),
@@ -156,19 +222,61 @@ impl Chunk {
             Ok(Op::Subtract) => self.simple_instruction("OP_SUBTRACT", offset),
             Ok(Op::Multiply) => self.simple_instruction("OP_MULTIPLY", offset),
             Ok(Op::Divide) => self.simple_instruction("OP_DIVIDE", offset),
+            Ok(Op::Modulo) => self.simple_instruction("OP_MODULO", offset),
+            Ok(Op::Power) => self.simple_instruction("OP_POWER", offset),
+            Ok(Op::IntDivide) => self.simple_instruction("OP_INT_DIVIDE", offset),
+            Ok(Op::BitAnd) => self.simple_instruction("OP_BIT_AND", offset),
+            Ok(Op::BitOr) => self.simple_instruction("OP_BIT_OR", offset),
+            Ok(Op::BitXor) => self.simple_instruction("OP_BIT_XOR", offset),
+            Ok(Op::BitNot) => self.simple_instruction("OP_BIT_NOT", offset),
+            Ok(Op::ShiftLeft) => self.simple_instruction("OP_SHIFT_LEFT", offset),
+            Ok(Op::ShiftRight) => self.simple_instruction("OP_SHIFT_RIGHT", offset),
             Ok(Op::Not) => self.simple_instruction("OP_NOT", offset),
             Ok(Op::Negate) => self.simple_instruction("OP_NEGATE", offset),
             Ok(Op::Print) => self.simple_instruction("OP_PRINT", offset),
+            Ok(Op::BuildString) => self.byte_instruction("OP_BUILD_STRING", offset),
             Ok(Op::Jump) => self.jump_instruction("OP_JUMP", 1, offset),
             Ok(Op::JumpIfFalse) => self.jump_instruction("OP_JUMP_IF_FALSE", 1, offset),
+            Ok(Op::JumpIfNotNil) => self.jump_instruction("OP_JUMP_IF_NOT_NIL", 1, offset),
+            Ok(Op::JumpTable) => {
+                let constant = self.code[offset + 1];
+                let count =
+                    u16::from_be_bytes([self.code[offset + 2], self.code[offset + 3]]) as usize;
+                let end = offset + 4 + (count + 1) * 2;
+                println!(
+                    "{:16} {:4} '{}' {} cases",
+                    "OP_JUMP_TABLE", constant, self.constants[constant as usize], count
+                );
+                for case in 0..=count {
+                    let at = offset + 4 + case * 2;
+                    let jump = u16::from_be_bytes([self.code[at], self.code[at + 1]]) as usize;
+                    println!(
+                        "{:04}      |                     {} -> {}",
+                        at,
+                        if case == count { "default" } else { "case" },
+                        end + jump
+                    );
+                }
+                return end;
+            }
+            Ok(Op::InRange) => self.byte_instruction("OP_IN_RANGE", offset),
+            Ok(Op::IterNext) => self.iter_instruction("OP_ITER_NEXT", offset),
+            Ok(Op::IterCheck) => self.iter_instruction("OP_ITER_CHECK", offset),
             Ok(Op::Loop) => self.jump_instruction("OP_LOOP", -1, offset),
             Ok(Op::Call) => self.byte_instruction("OP_CALL", offset),
+            Ok(Op::TailCall) => self.byte_instruction("OP_TAIL_CALL", offset),
+            Ok(Op::Collect) => self.byte_instruction("OP_COLLECT", offset),
+            Ok(Op::Extend) => self.simple_instruction("OP_EXTEND", offset),
+            Ok(Op::CallSpread) => self.simple_instruction("OP_CALL_SPREAD", offset),
+            Ok(Op::TailCallSpread) => self.simple_instruction("OP_TAIL_CALL_SPREAD", offset),
             Ok(Op::Closure) => {
                 let mut offset = offset + 1;
                 let constant = self.code[offset];
                 offset += 1;
-                print!("{:16} {:4} ", "OP_CLOSURE", constant);
-                self.constants[constant as usize].println();
+                println!(
+                    "{:16} {:4} {}",
+                    "OP_CLOSURE", constant, self.constants[constant as usize]
+                );
 
                 let function = match &self.constants[constant as usize] {
                     Value::Function(fun) => fun,
@@ -190,6 +298,7 @@ impl Chunk {
                 return offset;
             }
             Ok(Op::CloseUpvalue) => self.simple_instruction("OP_CLOSE_UPVALUE", offset),
+            Ok(Op::Yield) => self.simple_instruction("OP_YIELD", offset),
             Ok(Op::Return) => self.simple_instruction("OP_RETURN", offset),
             Err(v) => {
                 println!("Unknown opcode {}", v);
@@ -208,9 +317,10 @@ impl Chunk {
             .code
             .get(offset + 1)
             .expect("Could not get constant index");
-        print!("{:16} {:4} '", name, constant);
-        &self.constants[constant as usize].print();
-        println!("'");
+        println!(
+            "{:16} {:4} '{}'",
+            name, constant, self.constants[constant as usize]
+        );
         return offset + 2;
     }
 
@@ -231,4 +341,11 @@ impl Chunk {
         );
         return offset + 3;
     }
+
+    fn iter_instruction(&self, name: &'static str, offset: usize) -> usize {
+        let slot = self.code[offset + 1];
+        let jump = u16::from_be_bytes([self.code[offset + 2], self.code[offset + 3]]);
+        println!("{:16} {:4} -> {}", name, slot, offset + 4 + jump as usize);
+        offset + 4
+    }
 }
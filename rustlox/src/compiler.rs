@@ -1,11 +1,12 @@
 use crate::chunk::*;
+use crate::error::{Diagnostic, Location};
 use crate::expr::{self, Expr};
+use crate::number;
 use crate::parser;
-use crate::scanner::{Token, TokenKind};
+use crate::scanner::{self, Token, TokenKind};
 use crate::stmt::{self, Stmt};
 use crate::string;
 use crate::value::*;
-use crate::vm::InterpretError;
 use std::cell::RefCell;
 use std::convert::TryInto;
 use std::rc::Rc;
@@ -15,12 +16,15 @@ struct Local<'a> {
     name: &'a str,
     depth: Option<usize>,
     is_captured: bool,
+    constant: bool,
 }
 
 #[derive(Copy, Clone)]
 struct Upvalue {
     index: u8,
     is_local: bool,
+    /// Whether the captured variable was declared with `const`.
+    constant: bool,
 }
 
 struct Compiler<'a> {
@@ -32,7 +36,7 @@ struct Compiler<'a> {
     upvalues: Vec<Upvalue>,
 }
 
-type CompileResult<T> = Result<T, InterpretError>;
+type CompileResult<T> = Result<T, ()>;
 
 impl<'a> Compiler<'a> {
     fn new(enclosing: Option<Rc<RefCell<Compiler<'a>>>>, name: &str) -> Compiler<'a> {
@@ -40,8 +44,11 @@ impl<'a> Compiler<'a> {
             enclosing,
             function: Function {
                 arity: 0,
+                optional: 0,
+                variadic: false,
                 chunk: Rc::new(Chunk::new()),
                 name: string::Handle::from_str(name),
+                lambda_line: None,
                 upvalue_count: 0,
             },
             scope_depth: 0,
@@ -49,6 +56,7 @@ impl<'a> Compiler<'a> {
                 depth: Some(0),
                 name: "",
                 is_captured: false,
+                constant: false,
             }],
             upvalues: Vec::new(),
         }
@@ -79,14 +87,23 @@ impl<'a> Compiler<'a> {
         Ok(None)
     }
 
-    fn add_upvalue(&mut self, index: u8, is_local: bool) -> Result<u8, &'static str> {
+    fn add_upvalue(
+        &mut self,
+        index: u8,
+        is_local: bool,
+        constant: bool,
+    ) -> Result<u8, &'static str> {
         for (upvalue_index, upvalue) in self.upvalues.iter().enumerate() {
             if upvalue.index == index && upvalue.is_local == is_local {
                 return Ok(upvalue_index as u8);
             }
         }
 
-        self.upvalues.push(Upvalue { is_local, index });
+        self.upvalues.push(Upvalue {
+            is_local,
+            index,
+            constant,
+        });
         self.function.upvalue_count += 1;
         (self.upvalues.len() - 1)
             .try_into()
@@ -99,24 +116,41 @@ impl<'a> Compiler<'a> {
         }
 
         if let Some(local) = self.with_enclosing(|c| c.resolve_local(name))? {
-            self.with_enclosing_mut(|c| c.locals[local as usize].is_captured = true);
-            return Ok(Some(self.add_upvalue(local, true)?));
+            let constant = self.with_enclosing_mut(|c| {
+                let local = &mut c.locals[local as usize];
+                local.is_captured = true;
+                local.constant
+            });
+            return Ok(Some(self.add_upvalue(local, true, constant)?));
         }
 
         if let Some(upvalue) = self.with_enclosing_mut(|c| c.resolve_upvalue(name))? {
-            return Ok(Some(self.add_upvalue(upvalue, false)?));
+            let constant = self.with_enclosing(|c| c.upvalues[upvalue as usize].constant);
+            return Ok(Some(self.add_upvalue(upvalue, false, constant)?));
         }
 
         Ok(None)
     }
 }
 
+/// A loop, or a `match`, that `break` can leave.
+struct LoopContext<'a> {
+    label: Option<&'a str>,
+    /// Where `continue` jumps to. A `match` has none, so `continue` goes on
+    /// to the loop around it.
+    start: Option<usize>,
+    /// How many locals are still on the stack at `start`.
+    continue_locals: usize,
+    /// How many locals are still on the stack where `break` lands.
+    break_locals: usize,
+    breaks: Vec<usize>,
+}
+
 struct CompilerWrapper<'a> {
     current: Option<Rc<RefCell<Compiler<'a>>>>,
     current_line: i32,
-    loop_start: usize,
-    breaks: Vec<(usize, usize)>,
-    loop_depth: usize,
+    loops: Vec<LoopContext<'a>>,
+    errors: Vec<Diagnostic>,
 }
 
 impl<'a> CompilerWrapper<'a> {
@@ -124,9 +158,8 @@ impl<'a> CompilerWrapper<'a> {
         CompilerWrapper {
             current: Some(Rc::new(RefCell::new(Compiler::new(None, "")))),
             current_line: 0,
-            loop_start: 0,
-            breaks: Vec::new(),
-            loop_depth: 0,
+            loops: Vec::new(),
+            errors: Vec::new(),
         }
     }
 
@@ -175,16 +208,16 @@ impl<'a> CompilerWrapper<'a> {
         self.emit_byte(byte2);
     }
 
-    fn emit_loop(&mut self, loop_start: usize) -> CompileResult<()> {
+    fn emit_loop(&mut self, loop_start: usize, token: &Token) -> CompileResult<()> {
         self.emit_op(Op::Loop);
 
         let offset: u16 = self
             .with_current_chunk(|chunk| chunk.code.len() - loop_start + 2)
             .try_into()
-            .or_else(|_| self.error(None, "Loop body too large."))?;
+            .or_else(|_| self.error(token, "Loop body too large."))?;
 
-        self.emit_byte((offset >> 8) as u8 & 0xff);
-        self.emit_byte(offset as u8 & 0xff);
+        self.emit_byte((offset >> 8) as u8);
+        self.emit_byte(offset as u8);
         Ok(())
     }
 
@@ -192,7 +225,14 @@ impl<'a> CompilerWrapper<'a> {
         self.emit_op(instruction);
         self.emit_byte(0xff);
         self.emit_byte(0xff);
-        return self.with_current_chunk(|chunk| chunk.code.len() - 2);
+        self.with_current_chunk(|chunk| chunk.code.len() - 2)
+    }
+
+    /// Like `emit_jump`, but with the for-in iterable's slot before the offset.
+    fn emit_iter(&mut self, instruction: Op, iterable: u8) -> usize {
+        self.emit_bytes(instruction as u8, iterable);
+        self.emit_bytes(0xff, 0xff);
+        self.get_current_len() - 2
     }
 
     fn emit_return(&mut self) {
@@ -200,22 +240,22 @@ impl<'a> CompilerWrapper<'a> {
         self.emit_op(Op::Return);
     }
 
-    fn make_constant(&mut self, value: Value, lexeme: &str) -> CompileResult<u8> {
+    fn make_constant(&mut self, value: Value, token: &Token) -> CompileResult<u8> {
         self.with_current_chunk_mut(|chunk| chunk.add_constant(value))
-            .or_else(|message| self.error(Some(lexeme), message))
+            .or_else(|message| self.error(token, message))
     }
 
-    fn emit_constant(&mut self, value: Value, lexeme: &str) -> CompileResult<()> {
-        let constant = self.make_constant(value, lexeme)?;
+    fn emit_constant(&mut self, value: Value, token: &Token) -> CompileResult<()> {
+        let constant = self.make_constant(value, token)?;
         self.emit_bytes(Op::Constant as u8, constant);
         Ok(())
     }
 
-    fn patch_jump(&mut self, offset: usize) -> CompileResult<()> {
+    fn patch_jump(&mut self, offset: usize, token: &Token) -> CompileResult<()> {
         let jump: u16 = self
             .with_current_chunk(|chunk| chunk.code.len() - offset - 2)
             .try_into()
-            .or_else(|_| self.error(None, "Too much code to jump over."))?;
+            .or_else(|_| self.error(token, "Too much code to jump over."))?;
 
         self.with_current_chunk_mut(|chunk| chunk.code[offset] = ((jump >> 8) & 0xff) as u8);
         self.with_current_chunk_mut(|chunk| chunk.code[offset + 1] = (jump & 0xff) as u8);
@@ -227,13 +267,13 @@ impl<'a> CompilerWrapper<'a> {
         self.with_current_chunk(|chunk| chunk.code.len())
     }
 
-    fn identifier_constant(&mut self, name: &str) -> CompileResult<u8> {
-        self.make_constant(Value::String(string::Handle::from_str(name)), name)
+    fn identifier_constant(&mut self, name: &Token) -> CompileResult<u8> {
+        self.make_constant(Value::String(Rc::from(name.lexeme)), name)
     }
 
     fn add_local(&mut self, name: Token<'a>) -> CompileResult<()> {
-        if self.current.as_ref().unwrap().borrow().locals.len() >= u8::MAX as usize {
-            self.error(None, "Too many local variables in function.")?;
+        if self.current.as_ref().unwrap().borrow().locals.len() > u8::MAX as usize {
+            self.error(&name, "Too many local variables in function.")?;
         }
 
         self.current
@@ -245,6 +285,7 @@ impl<'a> CompilerWrapper<'a> {
                 name: name.lexeme,
                 depth: None,
                 is_captured: false,
+                constant: false,
             });
         Ok(())
     }
@@ -269,10 +310,7 @@ impl<'a> CompilerWrapper<'a> {
         }
 
         if !unique {
-            return self.error(
-                Some(name.lexeme),
-                "Already variable with this name in this scope.",
-            );
+            return self.error(name, "Already variable with this name in this scope.");
         }
 
         self.add_local(*name)
@@ -285,7 +323,7 @@ impl<'a> CompilerWrapper<'a> {
             return Ok(0);
         }
 
-        self.identifier_constant(token.lexeme)
+        self.identifier_constant(token)
     }
 
     fn mark_initialized(&mut self) {
@@ -307,19 +345,72 @@ impl<'a> CompilerWrapper<'a> {
         self.emit_bytes(Op::DefineGlobal as u8, global)
     }
 
-    fn patch_breaks(&mut self) -> CompileResult<()> {
-        while self
-            .breaks
-            .last()
-            .map_or(false, |(_, depth)| *depth == self.loop_depth)
-        {
-            let (jump, _) = self.breaks.pop().unwrap();
-            self.patch_jump(jump)?;
-        }
+    #[inline(always)]
+    fn local_count(&self) -> usize {
+        self.with_current(|current| current.locals.len())
+    }
+
+    /// Everything declared from here on is unwound by `break` and `continue`.
+    /// `break_locals` may be lower than the current count when the loop keeps
+    /// locals of its own on the stack between iterations.
+    fn begin_loop(
+        &mut self,
+        label: Option<&'a Token<'a>>,
+        start: Option<usize>,
+        break_locals: usize,
+    ) {
+        let continue_locals = self.local_count();
+        self.loops.push(LoopContext {
+            label: label.map(|label| label.lexeme),
+            start,
+            continue_locals,
+            break_locals,
+            breaks: Vec::new(),
+        });
+    }
 
+    /// Must be called where `break` should land.
+    fn end_loop(&mut self, end: &Token) -> CompileResult<()> {
+        let context = self.loops.pop().unwrap();
+        for jump in context.breaks {
+            self.patch_jump(jump, end)?;
+        }
         Ok(())
     }
 
+    /// Pops the locals above `count` ahead of a jump out of their scope. The
+    /// compiler still considers them declared, since the code after the jump
+    /// is within their scope.
+    fn discard_locals(&mut self, count: usize) {
+        // A labeled jump out of an inner loop may leave a local that a closure
+        // further down captured on an earlier trip round that loop, before
+        // `is_captured` says so. Closing an uncaptured local is just a pop.
+        let innermost = self
+            .loops
+            .iter()
+            .rev()
+            .find(|context| context.start.is_some())
+            .map_or(0, |context| context.continue_locals);
+        let ops: Vec<Op> = self.with_current(|current| {
+            current.locals[count..]
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(index, local)| {
+                    if local.is_captured || count + index < innermost {
+                        Op::CloseUpvalue
+                    } else {
+                        Op::Pop
+                    }
+                })
+                .collect()
+        });
+
+        for op in ops {
+            self.emit_op(op);
+        }
+    }
+
     fn end_compiler(&mut self) -> Compiler<'a> {
         self.emit_return();
         let mut compiler = Rc::try_unwrap(std::mem::take(&mut self.current).unwrap())
@@ -329,7 +420,7 @@ impl<'a> CompilerWrapper<'a> {
         {
             #![cfg(feature = "trace-execution")]
             let function = &compiler.function;
-            function.chunk.disassemble(function.get_name());
+            function.chunk.disassemble(&function.get_name());
         }
         self.current = std::mem::take(&mut compiler.enclosing);
         compiler
@@ -366,30 +457,28 @@ impl<'a> CompilerWrapper<'a> {
     }
 
     fn compile(
-        &mut self,
+        mut self,
         statements: std::vec::IntoIter<Stmt<'a>>,
-    ) -> Result<Function, InterpretError> {
-        let mut error = false;
+    ) -> Result<Function, Vec<Diagnostic>> {
         for statement in statements {
-            match self.statement(&statement) {
-                Ok(_) => (),
-                Err(_) => error = true,
-            }
+            // The error has already been recorded, so keep going to report any others.
+            self.statement(&statement).ok();
         }
 
-        if error {
-            return Err(InterpretError::CompileError);
+        if !self.errors.is_empty() {
+            return Err(self.errors);
         }
         let compiler = self.end_compiler();
         Ok(compiler.function)
     }
 
-    fn error<T>(&mut self, lexeme: Option<&str>, message: &'static str) -> CompileResult<T> {
-        if let Some(lex) = lexeme {
-            eprint!("Error at '{}': ", lex);
-        }
-        eprintln!("{}", message);
-        Err(InterpretError::CompileError)
+    fn error<T>(&mut self, token: &Token, message: &'static str) -> CompileResult<T> {
+        self.errors.push(Diagnostic {
+            line: token.line,
+            location: Location::Token(token.lexeme.to_string()),
+            message: message.to_string(),
+        });
+        Err(())
     }
 
     fn statement(&mut self, statement: &Stmt<'a>) -> CompileResult<()> {
@@ -397,10 +486,13 @@ impl<'a> CompilerWrapper<'a> {
             Stmt::Block(statement) => self.block_statement(statement),
             Stmt::Break(statement) => self.break_statement(statement),
             Stmt::Continue(statement) => self.continue_statement(statement),
+            Stmt::DoWhile(statement) => self.do_while_statement(statement),
             Stmt::Expression(statement) => self.expression_statement(statement),
             Stmt::For(statement) => self.for_statement(statement),
+            Stmt::ForIn(statement) => self.for_in_statement(statement),
             Stmt::Function(statement) => self.fun_declaration(statement),
             Stmt::If(statement) => self.if_statement(statement),
+            Stmt::Match(statement) => self.match_statement(statement),
             Stmt::Print(statement) => self.print_statement(statement),
             Stmt::Return(statement) => self.return_statement(statement),
             Stmt::Var(statement) => self.var_declaration(statement),
@@ -418,57 +510,145 @@ impl<'a> CompilerWrapper<'a> {
         Ok(())
     }
 
+    /// Laid out like a `while` whose first check is jumped over, so that
+    /// `continue` can go back to the condition like in any other loop.
+    fn do_while_statement(&mut self, statement: &stmt::DoWhile<'a>) -> CompileResult<()> {
+        let jump_to_body = self.emit_jump(Op::Jump);
+        let loop_start = self.get_current_len();
+        self.begin_loop(statement.label, Some(loop_start), self.local_count());
+
+        self.expression(&statement.condition)?;
+        let end_jump = self.emit_jump(Op::JumpIfFalse);
+        self.emit_op(Op::Pop);
+
+        self.patch_jump(jump_to_body, statement.end)?;
+        self.statement(&statement.body)?;
+
+        self.emit_loop(loop_start, statement.end)?;
+        self.patch_jump(end_jump, statement.end)?;
+        self.emit_op(Op::Pop);
+
+        self.end_loop(statement.end)?;
+        Ok(())
+    }
+
     fn expression_statement(&mut self, statement: &stmt::Expression<'a>) -> CompileResult<()> {
         self.expression(&statement.expression)?;
         self.emit_op(Op::Pop);
         Ok(())
     }
 
-    fn break_statement(&mut self, statement: &stmt::Break) -> CompileResult<()> {
+    fn break_statement(&mut self, statement: &stmt::Break<'a>) -> CompileResult<()> {
         self.current_line = statement.keyword.line;
+        let target = match statement.label {
+            Some(label) => self.labeled_loop(label),
+            None => self.loops.len().checked_sub(1),
+        };
+        let target = match target {
+            Some(target) => target,
+            None => return self.error(statement.keyword, "Unexpected 'break' statement."),
+        };
+        self.discard_locals(self.loops[target].break_locals);
         let jump = self.emit_jump(Op::Jump);
-        let depth = self.loop_depth;
-        self.breaks.push((jump, depth));
+        self.loops[target].breaks.push(jump);
         Ok(())
     }
 
-    fn continue_statement(&mut self, statement: &stmt::Continue) -> CompileResult<()> {
+    fn continue_statement(&mut self, statement: &stmt::Continue<'a>) -> CompileResult<()> {
         self.current_line = statement.keyword.line;
-        self.emit_loop(self.loop_start)?;
+        let target = match statement.label {
+            Some(label) => self.labeled_loop(label).map(|target| &self.loops[target]),
+            None => self
+                .loops
+                .iter()
+                .rev()
+                .find(|context| context.start.is_some()),
+        };
+        let target = target.and_then(|context| Some((context.start?, context.continue_locals)));
+        let (start, locals) = match target {
+            Some(target) => target,
+            None => return self.error(statement.keyword, "Unexpected 'continue' statement."),
+        };
+        self.discard_locals(locals);
+        self.emit_loop(start, statement.keyword)?;
         Ok(())
     }
 
-    fn function(&mut self, function: &stmt::Function<'a>) -> CompileResult<()> {
-        self.current_line = function.name.line;
+    fn labeled_loop(&self, label: &Token) -> Option<usize> {
+        self.loops
+            .iter()
+            .rposition(|context| context.label == Some(label.lexeme))
+    }
+
+    /// `name` is `None` for a lambda. Missing optional arguments arrive as
+    /// nil, so the prologue fills in the default of every parameter that is
+    /// still nil.
+    fn function(
+        &mut self,
+        name: Option<&str>,
+        params: &[stmt::Param<'a>],
+        rest: Option<&'a Token<'a>>,
+        body: &[Stmt<'a>],
+        brace: &Token,
+    ) -> CompileResult<()> {
         self.current = Some(Rc::new(RefCell::new(Compiler::new(
             Some(self.current.as_ref().unwrap().clone()),
-            function.name.lexeme,
+            name.unwrap_or(""),
         ))));
-        self.with_current_function_mut(|fun| fun.arity = function.params.len());
+        let line = self.current_line;
+        let optional = params
+            .iter()
+            .filter(|param| param.default.is_some())
+            .count();
+        self.with_current_function_mut(|fun| {
+            fun.arity = params.len() - optional;
+            fun.optional = optional;
+            fun.variadic = rest.is_some();
+            fun.lambda_line = name.is_none().then_some(line);
+        });
         self.begin_scope();
 
-        for token in &function.params {
+        for token in params.iter().map(|param| param.name).chain(rest) {
             let constant = self.parse_variable(token)?;
             self.define_variable(constant);
         }
 
-        for stmt in &function.body {
+        for (slot, param) in params.iter().enumerate() {
+            if let Some(default) = &param.default {
+                let slot = slot as u8 + 1;
+                self.current_line = param.name.line;
+                self.emit_bytes(Op::GetLocal as u8, slot);
+                let present = self.emit_jump(Op::JumpIfNotNil);
+                self.emit_byte(Op::Pop as u8);
+                self.expression(default)?;
+                self.emit_bytes(Op::SetLocal as u8, slot);
+                self.patch_jump(present, param.name)?;
+                self.emit_byte(Op::Pop as u8);
+            }
+        }
+
+        for stmt in body {
             self.statement(stmt)?
         }
-        self.current_line = function.brace.line;
+        self.current_line = brace.line;
 
         let compiler = self.end_compiler();
-        let name = compiler.function.name.as_str().string;
-        let constant = self.make_constant(Value::Function(compiler.function), name)?;
+        let constant = self.make_constant(Value::Function(compiler.function), brace)?;
         self.emit_bytes(Op::Closure as u8, constant);
 
-        for Upvalue { index, is_local } in compiler.upvalues {
+        for Upvalue {
+            index, is_local, ..
+        } in compiler.upvalues
+        {
             self.emit_byte(is_local.into());
             self.emit_byte(index);
         }
         Ok(())
     }
 
+    /// A variable declared in the initializer is copied into a fresh local for
+    /// every iteration, so closures in the body capture that iteration's
+    /// value. The copy is written back before the increment runs.
     fn for_statement(&mut self, statement: &stmt::For<'a>) -> CompileResult<()> {
         self.begin_scope();
 
@@ -476,6 +656,14 @@ impl<'a> CompilerWrapper<'a> {
             self.statement(stmt)?;
         }
 
+        let loop_variable = match statement.initializer.as_deref() {
+            Some(Stmt::Var(var)) => Some((
+                var.name,
+                self.with_current(|current| current.locals.len() - 1) as u8,
+            )),
+            _ => None,
+        };
+
         let mut before_condition: Option<usize> = None;
         let mut jump_after_cond: Option<usize> = None;
         let mut jump_to_body: Option<usize> = None;
@@ -489,45 +677,116 @@ impl<'a> CompilerWrapper<'a> {
         }
 
         let mut before_increment: Option<usize> = None;
+        let mut drop_copy: Option<usize> = None;
 
-        if let Some(incr) = &statement.increment {
-            jump_to_body.get_or_insert(self.emit_jump(Op::Jump));
+        if statement.increment.is_some() || loop_variable.is_some() {
+            if jump_to_body.is_none() {
+                jump_to_body = Some(self.emit_jump(Op::Jump));
+            }
             before_increment = Some(self.get_current_len());
-            self.expression(incr)?;
-            self.emit_op(Op::Pop);
+            if let Some((_, variable)) = loop_variable {
+                self.emit_bytes(Op::GetLocal as u8, variable + 1);
+                self.emit_bytes(Op::SetLocal as u8, variable);
+                self.emit_op(Op::Pop);
+                // Becomes a CloseUpvalue once the body shows the copy is captured.
+                drop_copy = Some(self.get_current_len());
+                self.emit_op(Op::Pop);
+            }
+            if let Some(incr) = &statement.increment {
+                self.expression(incr)?;
+                self.emit_op(Op::Pop);
+            }
             if let Some(loop_point) = before_condition {
-                self.emit_loop(loop_point)?
+                self.emit_loop(loop_point, statement.paren)?
             }
         }
 
         let before_body = self.get_current_len();
 
         if let Some(jump) = jump_to_body {
-            self.patch_jump(jump)?;
+            self.patch_jump(jump, statement.paren)?;
+        }
+
+        if let Some((name, variable)) = loop_variable {
+            self.begin_scope();
+            self.emit_bytes(Op::GetLocal as u8, variable);
+            self.add_local(*name)?;
+            self.mark_initialized();
         }
 
-        self.loop_depth += 1;
-        let enclosing_loop_start = self.loop_start;
-        self.loop_start = if let Some(incr) = before_increment {
+        let loop_start = if let Some(incr) = before_increment {
             incr
         } else if let Some(cond) = before_condition {
             cond
         } else {
             before_body
         };
+        // `continue` keeps the copy for the code before the increment, while
+        // `break` pops it along with everything else.
+        let break_locals = self.local_count() - loop_variable.iter().count();
+        self.begin_loop(statement.label, Some(loop_start), break_locals);
 
         self.statement(&statement.body)?;
 
-        self.emit_loop(self.loop_start)?;
+        // The copy is popped by the code before the increment rather than by
+        // ending its scope.
+        let drop_copy_op = loop_variable.map(|_| {
+            self.with_current_mut(|current| {
+                current.scope_depth -= 1;
+                match current.locals.pop() {
+                    Some(local) if local.is_captured => Op::CloseUpvalue,
+                    _ => Op::Pop,
+                }
+            })
+        });
+        if let (Some(offset), Some(op)) = (drop_copy, drop_copy_op) {
+            self.with_current_chunk_mut(|chunk| chunk.code[offset] = op as u8);
+        }
+
+        self.emit_loop(loop_start, statement.end)?;
 
         if let Some(jump) = jump_after_cond {
-            self.patch_jump(jump)?;
+            self.patch_jump(jump, statement.end)?;
             self.emit_op(Op::Pop);
         }
 
-        self.patch_breaks()?;
-        self.loop_start = enclosing_loop_start;
-        self.loop_depth -= 1;
+        self.end_loop(statement.end)?;
+        self.end_scope();
+        Ok(())
+    }
+
+    /// The iterable, the position within it and the loop variable occupy three
+    /// consecutive locals. The first two are named after the `in` keyword so
+    /// nothing in the body can refer to them.
+    fn for_in_statement(&mut self, statement: &stmt::ForIn<'a>) -> CompileResult<()> {
+        self.current_line = statement.keyword.line;
+        self.begin_scope();
+        self.expression(&statement.iterable)?;
+        self.add_local(*statement.keyword)?;
+        self.mark_initialized();
+        let iterable = (self.local_count() - 1) as u8;
+        self.emit_constant(Value::Int(0), statement.keyword)?;
+        self.add_local(*statement.keyword)?;
+        self.mark_initialized();
+        self.emit_op(Op::Nil);
+        self.declare_variable(statement.name)?;
+        self.mark_initialized();
+        let variable = iterable + 2;
+
+        let loop_start = self.get_current_len();
+        self.begin_loop(statement.label, Some(loop_start), self.local_count());
+
+        let exhausted = self.emit_iter(Op::IterNext, iterable);
+        let returned_nil = self.emit_iter(Op::IterCheck, iterable);
+        self.emit_bytes(Op::SetLocal as u8, variable);
+        self.emit_op(Op::Pop);
+
+        self.statement(&statement.body)?;
+        self.emit_loop(loop_start, statement.end)?;
+
+        self.patch_jump(exhausted, statement.end)?;
+        self.patch_jump(returned_nil, statement.end)?;
+        self.end_loop(statement.end)?;
 
         self.end_scope();
         Ok(())
@@ -536,7 +795,14 @@ impl<'a> CompilerWrapper<'a> {
     fn fun_declaration(&mut self, function: &stmt::Function<'a>) -> CompileResult<()> {
         let global = self.parse_variable(function.name)?;
         self.mark_initialized();
-        self.function(function)?;
+        self.current_line = function.name.line;
+        self.function(
+            Some(function.name.lexeme),
+            &function.params,
+            function.rest,
+            &function.body,
+            function.brace,
+        )?;
         self.define_variable(global);
         Ok(())
     }
@@ -549,27 +815,211 @@ impl<'a> CompilerWrapper<'a> {
         self.statement(&statement.then_branch)?;
 
         let jump_from_then = self.emit_jump(Op::Jump);
-        self.patch_jump(jump_to_else)?;
+        self.patch_jump(jump_to_else, statement.then_end)?;
         self.emit_op(Op::Pop);
 
         if let Some(stmt) = &statement.else_branch {
             self.statement(stmt)?;
         }
-        self.patch_jump(jump_from_then)?;
+        self.patch_jump(jump_from_then, statement.end)?;
         Ok(())
     }
 
-    fn print_statement(&mut self, statement: &stmt::Print) -> CompileResult<()> {
+    /// The subject lives in a hidden local for the duration of the match, so
+    /// every pattern can compare against it. `break` leaves the match the same
+    /// way it leaves a loop.
+    fn match_statement(&mut self, statement: &stmt::Match<'a>) -> CompileResult<()> {
+        self.current_line = statement.keyword.line;
+        self.begin_scope();
+        self.expression(&statement.subject)?;
+        // `match` is a keyword, so no user variable can resolve to this slot.
+        self.add_local(*statement.keyword)?;
+        self.mark_initialized();
+        let subject = (self.local_count() - 1) as u8;
+        self.begin_loop(None, None, self.local_count());
+
+        match self.jump_table_cases(statement) {
+            Some(cases) => self.match_jump_table(statement, subject, &cases)?,
+            None => self.match_sequence(statement, subject)?,
+        }
+
+        self.end_loop(statement.brace)?;
+        self.end_scope();
+        Ok(())
+    }
+
+    /// Returns the int each case matches when every case is a single, unguarded
+    /// int literal and the values are dense enough to be worth a jump table.
+    fn jump_table_cases(&self, statement: &stmt::Match<'a>) -> Option<Vec<i64>> {
+        if statement.cases.len() < 4 {
+            return None;
+        }
+
+        let mut values = Vec::new();
+        for case in &statement.cases {
+            if case.guard.is_some() || case.patterns.len() != 1 {
+                return None;
+            }
+            match &case.patterns[0] {
+                stmt::Pattern::Value(expr) => values.push(int_literal(expr)?),
+                _ => return None,
+            }
+        }
+
+        let low = *values.iter().min()?;
+        let high = *values.iter().max()?;
+        let span = high.checked_sub(low)?.checked_add(1)?;
+        if span > (values.len() as i64) * 2 || span > 256 {
+            return None;
+        }
+        Some(values)
+    }
+
+    fn match_jump_table(
+        &mut self,
+        statement: &stmt::Match<'a>,
+        subject: u8,
+        cases: &[i64],
+    ) -> CompileResult<()> {
+        let low = *cases.iter().min().unwrap();
+        let count = (*cases.iter().max().unwrap() - low + 1) as usize;
+
+        self.emit_bytes(Op::GetLocal as u8, subject);
+        let constant = self.make_constant(Value::Int(low), statement.keyword)?;
+        self.emit_bytes(Op::JumpTable as u8, constant);
+        self.emit_bytes((count >> 8) as u8, count as u8);
+        let table = self.get_current_len();
+        for _ in 0..=count {
+            self.emit_bytes(0xff, 0xff);
+        }
+        let table_end = self.get_current_len();
+
+        let mut targets = vec![None; count + 1];
+        let mut end_jumps = Vec::new();
+        for (case, value) in statement.cases.iter().zip(cases) {
+            self.current_line = case.keyword.line;
+            // Like the sequential match, the first of two equal cases wins.
+            let slot = (value - low) as usize;
+            if targets[slot].is_none() {
+                targets[slot] = Some(self.get_current_len() - table_end);
+            }
+            self.case_body(&case.body)?;
+            end_jumps.push(self.emit_jump(Op::Jump));
+        }
+
+        targets[count] = Some(self.get_current_len() - table_end);
+        if let Some(body) = &statement.default {
+            self.case_body(body)?;
+        }
+        let default = targets[count];
+
+        for (slot, target) in targets.into_iter().enumerate() {
+            let offset: u16 = target
+                .or(default)
+                .unwrap()
+                .try_into()
+                .or_else(|_| self.error(statement.brace, "Too much code to jump over."))?;
+            self.with_current_chunk_mut(|chunk| {
+                chunk.code[table + slot * 2] = (offset >> 8) as u8;
+                chunk.code[table + slot * 2 + 1] = offset as u8;
+            });
+        }
+        for jump in end_jumps {
+            self.patch_jump(jump, statement.brace)?;
+        }
+        Ok(())
+    }
+
+    fn match_sequence(&mut self, statement: &stmt::Match<'a>, subject: u8) -> CompileResult<()> {
+        let mut end_jumps = Vec::new();
+        for case in &statement.cases {
+            self.current_line = case.keyword.line;
+            let mut matched_jumps = Vec::new();
+            let mut next_pattern = None;
+            for pattern in &case.patterns {
+                if let Some(jump) = next_pattern.take() {
+                    self.patch_jump(jump, case.keyword)?;
+                    self.emit_op(Op::Pop);
+                }
+                self.pattern(pattern, subject)?;
+                next_pattern = Some(self.emit_jump(Op::JumpIfFalse));
+                self.emit_op(Op::Pop);
+                matched_jumps.push(self.emit_jump(Op::Jump));
+            }
+            for jump in matched_jumps {
+                self.patch_jump(jump, case.keyword)?;
+            }
+
+            let mut fail_jumps: Vec<usize> = next_pattern.into_iter().collect();
+            if let Some(guard) = &case.guard {
+                self.expression(guard)?;
+                fail_jumps.push(self.emit_jump(Op::JumpIfFalse));
+                self.emit_op(Op::Pop);
+            }
+
+            self.case_body(&case.body)?;
+            end_jumps.push(self.emit_jump(Op::Jump));
+
+            // Both a failed pattern and a failed guard leave their test on
+            // the stack.
+            for jump in fail_jumps {
+                self.patch_jump(jump, case.keyword)?;
+            }
+            self.emit_op(Op::Pop);
+        }
+
+        if let Some(body) = &statement.default {
+            self.case_body(body)?;
+        }
+        for jump in end_jumps {
+            self.patch_jump(jump, statement.brace)?;
+        }
+        Ok(())
+    }
+
+    /// Leaves whether the subject matches `pattern` on the stack.
+    fn pattern(&mut self, pattern: &stmt::Pattern<'a>, subject: u8) -> CompileResult<()> {
+        match pattern {
+            stmt::Pattern::Value(value) => {
+                self.emit_bytes(Op::GetLocal as u8, subject);
+                self.expression(value)?;
+                self.emit_op(Op::Equal);
+            }
+            stmt::Pattern::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                self.emit_bytes(Op::GetLocal as u8, subject);
+                self.expression(start)?;
+                self.expression(end)?;
+                self.emit_bytes(Op::InRange as u8, *inclusive as u8);
+            }
+            stmt::Pattern::Wildcard => self.emit_op(Op::True),
+        }
+        Ok(())
+    }
+
+    fn case_body(&mut self, body: &[Stmt<'a>]) -> CompileResult<()> {
+        self.begin_scope();
+        for statement in body {
+            self.statement(statement)?;
+        }
+        self.end_scope();
+        Ok(())
+    }
+
+    fn print_statement(&mut self, statement: &stmt::Print<'a>) -> CompileResult<()> {
         self.current_line = statement.keyword.line;
         self.expression(&statement.expression)?;
         self.emit_op(Op::Print);
         Ok(())
     }
 
-    fn return_statement(&mut self, statement: &stmt::Return) -> CompileResult<()> {
+    fn return_statement(&mut self, statement: &stmt::Return<'a>) -> CompileResult<()> {
         self.current_line = statement.keyword.line;
         if let Some(value) = &statement.value {
-            self.expression(value)?
+            self.tail_expression(value)?
         } else {
             self.emit_op(Op::Nil)
         }
@@ -579,9 +1029,8 @@ impl<'a> CompilerWrapper<'a> {
     }
 
     fn while_statement(&mut self, statement: &stmt::While<'a>) -> CompileResult<()> {
-        let enclosing_loop_start = self.loop_start;
-        self.loop_start = self.get_current_len();
-        self.loop_depth += 1;
+        let loop_start = self.get_current_len();
+        self.begin_loop(statement.label, Some(loop_start), self.local_count());
 
         self.expression(&statement.condition)?;
         let end_jump = self.emit_jump(Op::JumpIfFalse);
@@ -589,18 +1038,20 @@ impl<'a> CompilerWrapper<'a> {
 
         self.statement(&statement.body)?;
 
-        self.emit_loop(self.loop_start)?;
-        self.patch_jump(end_jump)?;
+        self.emit_loop(loop_start, statement.end)?;
+        self.patch_jump(end_jump, statement.end)?;
         self.emit_op(Op::Pop);
 
-        self.patch_breaks()?;
-        self.loop_start = enclosing_loop_start;
-        self.loop_depth -= 1;
+        self.end_loop(statement.end)?;
         Ok(())
     }
 
     fn var_declaration(&mut self, statement: &stmt::Var<'a>) -> CompileResult<()> {
         let global = self.parse_variable(statement.name)?;
+        let local = self.with_current(|current| current.scope_depth > 0);
+        if statement.constant && local {
+            self.with_current_mut(|current| current.locals.last_mut().unwrap().constant = true);
+        }
 
         if let Some(expr) = &statement.initializer {
             self.expression(expr)?;
@@ -608,56 +1059,134 @@ impl<'a> CompilerWrapper<'a> {
             self.emit_op(Op::Nil);
         }
 
-        self.define_variable(global);
+        // The compiler can't see every assignment to a global, so the VM
+        // enforces those.
+        if statement.constant && !local {
+            self.emit_bytes(Op::DefineConstant as u8, global);
+        } else {
+            self.define_variable(global);
+        }
         Ok(())
     }
 
-    fn expression(&mut self, expression: &Expr) -> CompileResult<()> {
+    fn expression(&mut self, expression: &Expr<'a>) -> CompileResult<()> {
         match expression {
             Expr::Assign(expr) => self.assignment(expr),
             Expr::Binary(expr) => self.binary(expr),
-            Expr::Call(expr) => self.call(expr),
+            Expr::Call(expr) => self.call(expr, false),
+            Expr::Conditional(expr) => self.conditional(expr, false),
             Expr::Grouping(expr) => self.expression(&expr.expr),
+            Expr::Interpolation(expr) => self.interpolation(expr),
+            Expr::Lambda(expr) => self.lambda(expr),
             Expr::Literal(expr) => self.literal(expr),
             Expr::Logical(expr) => self.logical(expr),
             Expr::Unary(expr) => self.unary(expr),
+            Expr::Update(expr) => self.update(expr),
             Expr::Variable(expr) => self.variable(expr),
+            Expr::Yield(expr) => self.yield_expression(expr),
         }
     }
 
     fn get_arg(
         &mut self,
-        name: &str,
+        name: &Token,
         local: Op,
         upvalue: Op,
         global: Op,
-    ) -> Result<(Op, u8), InterpretError> {
-        match self.with_current(|c| c.resolve_local(name)) {
+    ) -> CompileResult<(Op, u8)> {
+        match self.with_current(|c| c.resolve_local(name.lexeme)) {
             Ok(Some(result)) => return Ok((local, result)),
-            Err(message) => return self.error(Some(name), message),
+            Err(message) => return self.error(name, message),
             _ => (),
         };
 
-        match self.with_current_mut(|c| c.resolve_upvalue(name)) {
+        match self.with_current_mut(|c| c.resolve_upvalue(name.lexeme)) {
             Ok(Some(result)) => return Ok((upvalue, result)),
-            Err(message) => return self.error(Some(name), message),
+            Err(message) => return self.error(name, message),
             _ => (),
         }
 
         Ok((global, self.identifier_constant(name)?))
     }
 
-    fn assignment(&mut self, assignment: &expr::Assign) -> CompileResult<()> {
+    /// `get_arg` for the set ops, refusing to assign to a local or upvalue
+    /// declared with `const`.
+    fn set_arg(&mut self, name: &Token) -> CompileResult<(Op, u8)> {
+        let (op, arg) = self.get_arg(name, Op::SetLocal, Op::SetUpvalue, Op::SetGlobal)?;
+        let constant = self.with_current(|current| match op {
+            Op::SetLocal => current.locals[arg as usize].constant,
+            Op::SetUpvalue => current.upvalues[arg as usize].constant,
+            _ => false,
+        });
+        if constant {
+            return self.error(name, "Can't assign to a constant.");
+        }
+        Ok((op, arg))
+    }
+
+    /// Resolves `name` once and returns the matching get and set ops.
+    fn variable_ops(&mut self, name: &Token) -> CompileResult<(Op, Op, u8)> {
+        let (set_op, arg) = self.set_arg(name)?;
+        let get_op = match set_op {
+            Op::SetLocal => Op::GetLocal,
+            Op::SetUpvalue => Op::GetUpvalue,
+            _ => Op::GetGlobal,
+        };
+        Ok((get_op, set_op, arg))
+    }
+
+    fn assignment(&mut self, assignment: &expr::Assign<'a>) -> CompileResult<()> {
+        let operator = match assignment.operator.kind {
+            TokenKind::PlusEqual => Op::Add,
+            TokenKind::MinusEqual => Op::Subtract,
+            TokenKind::StarEqual => Op::Multiply,
+            TokenKind::SlashEqual => Op::Divide,
+            TokenKind::PercentEqual => Op::Modulo,
+            _ => {
+                self.expression(&assignment.value)?;
+
+                self.current_line = assignment.name.line;
+                let (set_op, arg) = self.set_arg(assignment.name)?;
+
+                self.emit_bytes(set_op as u8, arg);
+                return Ok(());
+            }
+        };
+
+        self.current_line = assignment.name.line;
+        let (get_op, set_op, arg) = self.variable_ops(assignment.name)?;
+        self.emit_bytes(get_op as u8, arg);
         self.expression(&assignment.value)?;
+        self.current_line = assignment.operator.line;
+        self.emit_op(operator);
+        self.emit_bytes(set_op as u8, arg);
+        Ok(())
+    }
 
-        let name = assignment.name.lexeme;
-        let (set_op, arg) = self.get_arg(name, Op::SetLocal, Op::SetUpvalue, Op::SetGlobal)?;
+    /// Prefix forms leave the new value on the stack, postfix forms the old
+    /// one.
+    fn update(&mut self, update: &expr::Update<'a>) -> CompileResult<()> {
+        self.current_line = update.name.line;
+        let (get_op, set_op, arg) = self.variable_ops(update.name)?;
 
+        self.current_line = update.operator.line;
+        self.emit_bytes(get_op as u8, arg);
+        if !update.prefix {
+            self.emit_bytes(get_op as u8, arg);
+        }
+        self.emit_constant(Value::Int(1), update.operator)?;
+        self.emit_op(match update.operator.kind {
+            TokenKind::PlusPlus => Op::Add,
+            _ => Op::Subtract,
+        });
         self.emit_bytes(set_op as u8, arg);
+        if !update.prefix {
+            self.emit_op(Op::Pop);
+        }
         Ok(())
     }
 
-    fn binary(&mut self, binary: &expr::Binary) -> CompileResult<()> {
+    fn binary(&mut self, binary: &expr::Binary<'a>) -> CompileResult<()> {
         self.expression(&binary.left)?;
         self.expression(&binary.right)?;
 
@@ -673,100 +1202,250 @@ impl<'a> CompilerWrapper<'a> {
             TokenKind::Minus => self.emit_op(Op::Subtract),
             TokenKind::Slash => self.emit_op(Op::Divide),
             TokenKind::Star => self.emit_op(Op::Multiply),
+            TokenKind::Percent => self.emit_op(Op::Modulo),
+            TokenKind::StarStar => self.emit_op(Op::Power),
+            TokenKind::TildeSlash => self.emit_op(Op::IntDivide),
+            TokenKind::Ampersand => self.emit_op(Op::BitAnd),
+            TokenKind::Pipe => self.emit_op(Op::BitOr),
+            TokenKind::Caret => self.emit_op(Op::BitXor),
+            TokenKind::LessLess => self.emit_op(Op::ShiftLeft),
+            TokenKind::GreaterGreater => self.emit_op(Op::ShiftRight),
             _ => unreachable!(),
         };
         Ok(())
     }
 
-    fn call(&mut self, call: &expr::Call) -> CompileResult<()> {
+    /// Compiles a returned value. Calls in tail position, including those in
+    /// either branch of a conditional, become tail calls.
+    fn tail_expression(&mut self, expr: &Expr<'a>) -> CompileResult<()> {
+        match expr {
+            Expr::Call(call) => self.call(call, true),
+            Expr::Conditional(conditional) => self.conditional(conditional, true),
+            Expr::Grouping(grouping) => self.tail_expression(&grouping.expr),
+            _ => self.expression(expr),
+        }
+    }
+
+    /// A call that spreads any argument gathers all of them into one list,
+    /// which `CallSpread` unpacks again once its length is known. Calls
+    /// returned straight away are `tail` calls.
+    fn call(&mut self, call: &expr::Call<'a>, tail: bool) -> CompileResult<()> {
         self.expression(&call.callee)?;
+
+        if call.args.iter().all(|arg| arg.spread.is_none()) {
+            for arg in &call.args {
+                self.expression(&arg.value)?;
+            }
+            self.current_line = call.paren.line;
+            let op = if tail { Op::TailCall } else { Op::Call };
+            self.emit_bytes(op as u8, call.args.len() as u8);
+            return Ok(());
+        }
+
+        let mut pending: u8 = 0;
+        let mut collected = false;
         for arg in &call.args {
-            self.expression(arg)?;
+            match arg.spread {
+                Some(dots) => {
+                    if pending > 0 || !collected {
+                        self.emit_bytes(Op::Collect as u8, pending);
+                        if collected {
+                            self.emit_byte(Op::Extend as u8);
+                        }
+                        pending = 0;
+                        collected = true;
+                    }
+                    self.expression(&arg.value)?;
+                    self.current_line = dots.line;
+                    self.emit_byte(Op::Extend as u8);
+                }
+                None => {
+                    self.expression(&arg.value)?;
+                    pending += 1;
+                }
+            }
+        }
+        if pending > 0 {
+            self.emit_bytes(Op::Collect as u8, pending);
+            self.emit_byte(Op::Extend as u8);
+        }
+
+        self.current_line = call.paren.line;
+        self.emit_op(if tail {
+            Op::TailCallSpread
+        } else {
+            Op::CallSpread
+        });
+        Ok(())
+    }
+
+    fn interpolation(&mut self, interpolation: &expr::Interpolation<'a>) -> CompileResult<()> {
+        for part in &interpolation.parts {
+            self.expression(part)?;
         }
-        self.emit_bytes(Op::Call as u8, call.args.len() as u8);
+
+        let count: u8 =
+            interpolation.parts.len().try_into().or_else(|_| {
+                self.error(interpolation.end, "Too many parts in interpolated string.")
+            })?;
+        self.emit_bytes(Op::BuildString as u8, count);
         Ok(())
     }
 
-    fn literal(&mut self, literal: &expr::Literal) -> CompileResult<()> {
+    fn lambda(&mut self, lambda: &expr::Lambda<'a>) -> CompileResult<()> {
+        self.current_line = lambda.keyword.line;
+        self.function(
+            None,
+            &lambda.params,
+            lambda.rest,
+            &lambda.body,
+            lambda.brace,
+        )
+    }
+
+    fn yield_expression(&mut self, expr: &expr::Yield<'a>) -> CompileResult<()> {
+        match &expr.value {
+            Some(value) => self.expression(value)?,
+            None => self.emit_op(Op::Nil),
+        }
+        self.current_line = expr.keyword.line;
+        self.emit_op(Op::Yield);
+        Ok(())
+    }
+
+    fn literal(&mut self, literal: &expr::Literal<'a>) -> CompileResult<()> {
         self.current_line = literal.value.line;
         match literal.value.kind {
             TokenKind::Nil => self.emit_op(Op::Nil),
             TokenKind::False => self.emit_op(Op::False),
             TokenKind::True => self.emit_op(Op::True),
-            TokenKind::Number => self.number(literal.value.lexeme)?,
-            TokenKind::String => self.string(literal.value.lexeme)?,
+            TokenKind::Number => self.number(literal.value)?,
+            TokenKind::String | TokenKind::Interpolation => self.string(literal.value)?,
             _ => unreachable!(),
         };
         Ok(())
     }
 
-    fn logical(&mut self, logical: &expr::Logical) -> CompileResult<()> {
+    fn logical(&mut self, logical: &expr::Logical<'a>) -> CompileResult<()> {
         match logical.operator.kind {
             TokenKind::And => self.and(logical),
             TokenKind::Or => self.or(logical),
+            TokenKind::QuestionQuestion => self.coalesce(logical),
             _ => unreachable!(),
         }
     }
 
-    fn unary(&mut self, unary: &expr::Unary) -> CompileResult<()> {
+    fn unary(&mut self, unary: &expr::Unary<'a>) -> CompileResult<()> {
         self.current_line = unary.operator.line;
         self.expression(&unary.right)?;
         match unary.operator.kind {
             TokenKind::Bang => self.emit_op(Op::Not),
             TokenKind::Minus => self.emit_op(Op::Negate),
+            TokenKind::Tilde => self.emit_op(Op::BitNot),
             _ => unreachable!(),
         };
         Ok(())
     }
 
-    fn variable(&mut self, variable: &expr::Variable) -> CompileResult<()> {
-        let name = variable.name.lexeme;
+    fn variable(&mut self, variable: &expr::Variable<'a>) -> CompileResult<()> {
+        let name = variable.name;
         self.current_line = variable.name.line;
         let (get_op, arg) = self.get_arg(name, Op::GetLocal, Op::GetUpvalue, Op::GetGlobal)?;
         self.emit_bytes(get_op as u8, arg);
         Ok(())
     }
 
-    fn and(&mut self, logical: &expr::Logical) -> CompileResult<()> {
+    fn and(&mut self, logical: &expr::Logical<'a>) -> CompileResult<()> {
         self.expression(&logical.left)?;
         let jump = self.emit_jump(Op::JumpIfFalse);
         self.emit_op(Op::Pop);
 
         self.expression(&logical.right)?;
-        self.patch_jump(jump)?;
+        self.patch_jump(jump, logical.operator)?;
         Ok(())
     }
 
-    fn or(&mut self, logical: &expr::Logical) -> CompileResult<()> {
+    fn coalesce(&mut self, logical: &expr::Logical<'a>) -> CompileResult<()> {
         self.expression(&logical.left)?;
+        let jump = self.emit_jump(Op::JumpIfNotNil);
+        self.emit_op(Op::Pop);
+
+        self.expression(&logical.right)?;
+        self.patch_jump(jump, logical.operator)?;
+        Ok(())
+    }
+
+    fn conditional(
+        &mut self,
+        conditional: &expr::Conditional<'a>,
+        tail: bool,
+    ) -> CompileResult<()> {
+        let branch = |compiler: &mut Self, expr| match tail {
+            true => compiler.tail_expression(expr),
+            false => compiler.expression(expr),
+        };
+
+        self.expression(&conditional.condition)?;
+        self.current_line = conditional.question.line;
         let else_jump = self.emit_jump(Op::JumpIfFalse);
+        self.emit_op(Op::Pop);
+        branch(self, &conditional.then_branch)?;
         let end_jump = self.emit_jump(Op::Jump);
 
-        self.patch_jump(else_jump)?;
+        self.patch_jump(else_jump, conditional.question)?;
+        self.emit_op(Op::Pop);
+        branch(self, &conditional.else_branch)?;
+
+        self.patch_jump(end_jump, conditional.question)?;
+        Ok(())
+    }
+
+    fn or(&mut self, logical: &expr::Logical<'a>) -> CompileResult<()> {
+        self.expression(&logical.left)?;
+        let else_jump = self.emit_jump(Op::JumpIfFalse);
+        let end_jump = self.emit_jump(Op::Jump);
+
+        self.patch_jump(else_jump, logical.operator)?;
         self.emit_op(Op::Pop);
         self.expression(&logical.right)?;
 
-        self.patch_jump(end_jump)?;
+        self.patch_jump(end_jump, logical.operator)?;
         Ok(())
     }
 
-    fn number(&mut self, lexeme: &str) -> CompileResult<()> {
-        let value: f64 = lexeme.parse().expect("Failed to parse string into float");
-        self.emit_constant(Value::Number(value), lexeme)?;
+    fn number(&mut self, token: &Token) -> CompileResult<()> {
+        let value = number::literal(token.lexeme).or_else(|message| self.error(token, message))?;
+        self.emit_constant(value, token)?;
         Ok(())
     }
 
-    fn string(&mut self, lexeme: &str) -> CompileResult<()> {
-        let handle = string::Handle::from_str(&lexeme[1..lexeme.len() - 1]);
-        self.emit_constant(Value::String(handle), lexeme)?;
+    fn string(&mut self, token: &Token) -> CompileResult<()> {
+        let value =
+            scanner::string_value(token.lexeme).or_else(|message| self.error(token, message))?;
+        self.emit_constant(Value::String(Rc::from(value)), token)?;
         Ok(())
     }
 }
 
-pub fn compile<'a>(tokens: Vec<Token<'a>>) -> Result<Function, InterpretError> {
-    let statements = parser::parse_tokens(&tokens)
-        .ok_or(InterpretError::CompileError)?
-        .into_iter();
-    let mut compiler = CompilerWrapper::new();
-    compiler.compile(statements)
+/// The value of an int literal, possibly negated, or None for any other
+/// expression.
+fn int_literal(expr: &Expr) -> Option<i64> {
+    match expr {
+        Expr::Literal(literal) if literal.value.kind == TokenKind::Number => {
+            match number::literal(literal.value.lexeme) {
+                Ok(Value::Int(value)) => Some(value),
+                _ => None,
+            }
+        }
+        Expr::Grouping(grouping) => int_literal(&grouping.expr),
+        Expr::Unary(unary) if unary.operator.kind == TokenKind::Minus => {
+            int_literal(&unary.right)?.checked_neg()
+        }
+        _ => None,
+    }
+}
+
+pub fn compile(tokens: Vec<Token>) -> Result<Function, Vec<Diagnostic>> {
+    let statements = parser::parse_tokens(&tokens)?.into_iter();
+    CompilerWrapper::new().compile(statements)
 }
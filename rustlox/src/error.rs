@@ -0,0 +1,91 @@
+use crate::limits::Limit;
+use std::fmt::Display;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Location {
+    Token(String),
+    End,
+    Unknown,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub line: i32,
+    pub location: Location,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "[line {}] Error", self.line)?;
+        match &self.location {
+            Location::Token(lexeme) => write!(f, " at '{}'", lexeme)?,
+            Location::End => write!(f, " at end")?,
+            Location::Unknown => (),
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RuntimeErrorKind {
+    Type,
+    Arithmetic,
+    Arity,
+    UndefinedVariable,
+    ConstantAssignment,
+    NotCallable,
+    StackOverflow,
+    Fiber,
+    Permission,
+    Native,
+    Limit(Limit),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    pub line: i32,
+    /// Frames of callers that tail called into this one and so no longer exist.
+    pub tail_calls: usize,
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self.function.as_str() {
+            "<script>" => write!(f, "[line {}] in script", self.line),
+            name if name.starts_with('<') => write!(f, "[line {}] in {}", self.line, name),
+            name => write!(f, "[line {}] in {}()", self.line, name),
+        }?;
+        match self.tail_calls {
+            0 => Ok(()),
+            1 => write!(f, "\n[1 frame elided by a tail call]"),
+            count => write!(f, "\n[{} frames elided by tail calls]", count),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub message: String,
+    /// Innermost call first.
+    pub frames: Vec<Frame>,
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "{}", self.message)?;
+        for frame in &self.frames {
+            write!(f, "\n{}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum InterpretError {
+    Compile(Vec<Diagnostic>),
+    Runtime(RuntimeError),
+    Internal(&'static str),
+}
@@ -1,8 +1,11 @@
 use crate::scanner::Token;
+use crate::stmt::{Param, Stmt};
 
 #[derive(Debug)]
 pub struct Assign<'a> {
     pub name: &'a Token<'a>,
+    /// The `+=`-style operator of a compound assignment, `=` otherwise.
+    pub operator: &'a Token<'a>,
     pub value: Box<Expr<'a>>,
 }
 
@@ -17,7 +20,22 @@ pub struct Binary<'a> {
 pub struct Call<'a> {
     pub callee: Box<Expr<'a>>,
     pub paren: &'a Token<'a>,
-    pub args: Vec<Expr<'a>>,
+    pub args: Vec<Argument<'a>>,
+}
+
+#[derive(Debug)]
+pub struct Argument<'a> {
+    pub value: Expr<'a>,
+    /// The `...` in front of an argument whose list is spread into the call.
+    pub spread: Option<&'a Token<'a>>,
+}
+
+#[derive(Debug)]
+pub struct Conditional<'a> {
+    pub condition: Box<Expr<'a>>,
+    pub question: &'a Token<'a>,
+    pub then_branch: Box<Expr<'a>>,
+    pub else_branch: Box<Expr<'a>>,
 }
 
 #[derive(Debug)]
@@ -25,6 +43,21 @@ pub struct Grouping<'a> {
     pub expr: Box<Expr<'a>>,
 }
 
+#[derive(Debug)]
+pub struct Interpolation<'a> {
+    pub parts: Vec<Expr<'a>>,
+    pub end: &'a Token<'a>,
+}
+
+#[derive(Debug)]
+pub struct Lambda<'a> {
+    pub keyword: &'a Token<'a>,
+    pub params: Vec<Param<'a>>,
+    pub rest: Option<&'a Token<'a>>,
+    pub body: Vec<Stmt<'a>>,
+    pub brace: &'a Token<'a>,
+}
+
 #[derive(Debug)]
 pub struct Literal<'a> {
     pub value: &'a Token<'a>,
@@ -43,19 +76,39 @@ pub struct Unary<'a> {
     pub right: Box<Expr<'a>>,
 }
 
+/// `++` or `--`, before or after a variable.
+#[derive(Debug)]
+pub struct Update<'a> {
+    pub name: &'a Token<'a>,
+    pub operator: &'a Token<'a>,
+    pub prefix: bool,
+}
+
 #[derive(Debug)]
 pub struct Variable<'a> {
     pub name: &'a Token<'a>,
 }
 
+/// Suspends the running fiber. Evaluates to the value it's resumed with.
+#[derive(Debug)]
+pub struct Yield<'a> {
+    pub keyword: &'a Token<'a>,
+    pub value: Option<Box<Expr<'a>>>,
+}
+
 #[derive(Debug)]
 pub enum Expr<'a> {
     Assign(Assign<'a>),
     Binary(Binary<'a>),
     Call(Call<'a>),
+    Conditional(Conditional<'a>),
     Grouping(Grouping<'a>),
+    Interpolation(Interpolation<'a>),
+    Lambda(Lambda<'a>),
     Literal(Literal<'a>),
     Logical(Logical<'a>),
     Unary(Unary<'a>),
+    Update(Update<'a>),
     Variable(Variable<'a>),
+    Yield(Yield<'a>),
 }
@@ -0,0 +1,15 @@
+mod bigint;
+mod chunk;
+mod compiler;
+pub mod error;
+mod expr;
+pub mod limits;
+pub mod native;
+mod number;
+mod parser;
+pub mod permissions;
+mod scanner;
+mod stmt;
+pub mod string;
+pub mod value;
+pub mod vm;
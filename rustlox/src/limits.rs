@@ -0,0 +1,30 @@
+use std::fmt::Display;
+use std::time::Duration;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Limits {
+    pub max_instructions: Option<u64>,
+    /// Checked between instructions, so it doesn't bound the time spent
+    /// inside a single one, such as a call to a slow native.
+    pub timeout: Option<Duration>,
+    /// Bytes the script may allocate over a single run. Frees are not credited
+    /// back, so this is a budget rather than a live heap size.
+    pub max_heap: Option<usize>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Limit {
+    Instructions,
+    Timeout,
+    Heap,
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            Limit::Instructions => write!(f, "Instruction limit exceeded."),
+            Limit::Timeout => write!(f, "Execution timed out."),
+            Limit::Heap => write!(f, "Heap limit exceeded."),
+        }
+    }
+}
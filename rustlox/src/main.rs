@@ -1,58 +1,108 @@
-mod chunk;
-mod compiler;
-mod expr;
-mod native;
-mod parser;
-mod scanner;
-mod stmt;
-mod string;
-mod value;
-mod vm;
-
-use vm::*;
-
-fn repl() {
-    use std::io::{self, BufRead, Write};
-
-    let stdin = io::stdin();
-    let mut lines = stdin.lock().lines();
+use rustlox::error::InterpretError;
+use rustlox::limits::Limits;
+use rustlox::permissions::{Capability, Permissions};
+use rustlox::vm::VM;
+use std::time::Duration;
+
+const USAGE: &str = "Usage: rustlox [options] [path]
+
+Options:
+  --max-instructions=<count>  Stop after executing <count> instructions
+  --timeout=<ms>              Stop after running for <ms> milliseconds
+  --max-heap=<bytes>          Stop after allocating <bytes> bytes
+  --allow-all                 Grant every capability
+  --allow-<capability>        Grant fs-read, fs-write, env, process, net or time
+  --allow-fs-read=<paths>     Grant fs-read for a comma-separated list of paths
+  --allow-fs-write=<paths>    Grant fs-write for a comma-separated list of paths";
+
+fn repl(vm: &mut VM) {
+    use std::io::{self, Write};
+
     loop {
         print!("> ");
         io::stdout().flush().expect("Couldn't flush stdout");
-        let result = match lines.next() {
-            Some(Ok(line)) => vm::interpret(&line),
-            _ => break,
-        };
+        let mut line = String::new();
+        match vm.stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
 
-        if let Err(InterpretError::InternalError(message)) = result {
-            eprintln!("{}", message);
+        if let Err(error) = vm.interpret(&line) {
+            vm.report(&error);
         }
     }
 }
 
-fn run_file(path: &String) {
+fn run_file(vm: &mut VM, path: &str) {
     use std::fs;
 
     let source = fs::read_to_string(path).expect("Failed to read filed");
 
-    match vm::interpret(&source) {
-        Err(InterpretError::CompileError) => std::process::exit(65),
-        Err(InterpretError::RuntimeError) => std::process::exit(70),
-        Err(InterpretError::InternalError(message)) => {
-            eprintln!("Fatal error: {}", message);
-            std::process::exit(1)
-        }
-        Ok(()) => (),
+    if let Err(error) = vm.interpret(&source) {
+        vm.report(&error);
+        std::process::exit(match error {
+            InterpretError::Compile(_) => 65,
+            InterpretError::Runtime(_) => 70,
+            InterpretError::Internal(_) => 1,
+        })
     }
 }
 
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(64)
+}
+
+fn parse_flag<T: std::str::FromStr>(value: &str) -> T {
+    value.parse().unwrap_or_else(|_| usage())
+}
+
 fn main() {
     use std::env;
 
-    let args: Vec<String> = env::args().collect();
-    match args.len() {
-        1 => repl(),
-        2 => run_file(&args[1]),
-        _ => eprintln!("Usage: rustlox [path]"),
+    let mut limits = Limits::default();
+    let mut permissions = Permissions::default();
+    let mut path: Option<String> = None;
+
+    for arg in env::args().skip(1) {
+        let (flag, value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag, Some(value)),
+            None => (arg.as_str(), None),
+        };
+
+        match (flag, value) {
+            ("--max-instructions", Some(value)) => {
+                limits.max_instructions = Some(parse_flag(value))
+            }
+            ("--timeout", Some(value)) => {
+                limits.timeout = Some(Duration::from_millis(parse_flag(value)))
+            }
+            ("--max-heap", Some(value)) => limits.max_heap = Some(parse_flag(value)),
+            ("--allow-all", None) => permissions = Permissions::all(),
+            (flag, value) if flag.starts_with("--allow-") => {
+                let capability =
+                    Capability::from_name(&flag["--allow-".len()..]).unwrap_or_else(|| usage());
+                match value {
+                    None => permissions.grant(capability),
+                    Some(paths) if capability.is_scoped() => {
+                        for path in paths.split(',') {
+                            permissions.grant_path(capability, path);
+                        }
+                    }
+                    Some(_) => usage(),
+                }
+            }
+            _ if arg.starts_with("--") || path.is_some() => usage(),
+            _ => path = Some(arg),
+        }
+    }
+
+    let mut vm = VM::new();
+    vm.set_limits(limits);
+    vm.set_permissions(permissions);
+
+    match path {
+        Some(path) => run_file(&mut vm, &path),
+        None => repl(&mut vm),
     }
 }
@@ -1,12 +1,86 @@
+pub mod fiber;
+pub mod math;
+pub mod string;
+
+use crate::permissions::Capability;
 use crate::value::*;
+use crate::vm::VM;
+use std::env;
+use std::fs;
+use std::rc::Rc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-pub type Function = fn(args: &[Value]) -> Value;
+pub type NativeResult = Result<Value, String>;
+pub type NativeFn = fn(vm: &mut VM, args: &[Value]) -> NativeResult;
+
+#[derive(Copy, Clone)]
+pub struct Function {
+    pub name: &'static str,
+    pub arity: usize,
+    pub capability: Option<Capability>,
+    pub function: NativeFn,
+}
+
+fn expect_string<'v>(name: &str, value: &'v Value) -> Result<&'v str, String> {
+    match value {
+        Value::String(string) => Ok(string),
+        _ => Err(format!("Argument to '{}' must be a string.", name)),
+    }
+}
+
+fn expect_number(name: &str, value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Number(number) => Ok(*number),
+        Value::Int(number) => Ok(*number as f64),
+        Value::BigInt(number) => Ok(number.to_f64()),
+        _ => Err(format!("Argument to '{}' must be a number.", name)),
+    }
+}
 
-pub fn clock(_args: &[Value]) -> Value {
+pub fn clock(_vm: &mut VM, _args: &[Value]) -> NativeResult {
     let timestamp = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .expect("Time went backwards")
         .as_secs_f64();
-    Value::Number(timestamp)
+    Ok(Value::Number(timestamp))
+}
+
+pub fn read_file(vm: &mut VM, args: &[Value]) -> NativeResult {
+    let path = expect_string("readFile", &args[0])?;
+    let contents = fs::read_to_string(path)
+        .map_err(|error| format!("Could not read '{}': {}.", path, error))?;
+    vm.charge(contents.len());
+    Ok(Value::String(Rc::from(contents)))
+}
+
+pub fn write_file(_vm: &mut VM, args: &[Value]) -> NativeResult {
+    let path = expect_string("writeFile", &args[0])?;
+    let contents = expect_string("writeFile", &args[1])?;
+    fs::write(path, contents)
+        .map(|_| Value::Nil)
+        .map_err(|error| format!("Could not write '{}': {}.", path, error))
+}
+
+pub fn get_env(vm: &mut VM, args: &[Value]) -> NativeResult {
+    let name = expect_string("getEnv", &args[0])?;
+    Ok(match env::var(name) {
+        Ok(value) => {
+            vm.charge(value.len());
+            Value::String(Rc::from(value))
+        }
+        Err(_) => Value::Nil,
+    })
+}
+
+pub fn read_line(vm: &mut VM, _args: &[Value]) -> NativeResult {
+    let mut line = String::new();
+    match vm.stdin().read_line(&mut line) {
+        Ok(0) => Ok(Value::Nil),
+        Ok(_) => {
+            let line = line.trim_end_matches(&['\r', '\n'][..]);
+            vm.charge(line.len());
+            Ok(Value::String(Rc::from(line)))
+        }
+        Err(error) => Err(format!("Could not read from stdin: {}.", error)),
+    }
 }
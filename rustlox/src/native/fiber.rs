@@ -0,0 +1,43 @@
+//! Fiber natives. A fiber runs a function on its own stack until the
+//! function yields or returns, and picks up where it yielded when resumed.
+
+use super::NativeResult;
+use crate::value::Value;
+use crate::vm::{Fiber, VM};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub fn define(vm: &mut VM) {
+    vm.define_native("Fiber", 1, None, fiber);
+    vm.define_native("resume", 2, None, resume);
+    vm.define_native("isDone", 1, None, is_done);
+}
+
+fn expect_fiber<'v>(name: &str, value: &'v Value) -> Result<&'v Rc<RefCell<Fiber>>, String> {
+    match value {
+        Value::Fiber(fiber) => Ok(fiber),
+        _ => Err(format!("Argument to '{}' must be a fiber.", name)),
+    }
+}
+
+fn fiber(vm: &mut VM, args: &[Value]) -> NativeResult {
+    match &args[0] {
+        Value::Closure(closure) => {
+            let fiber = Fiber::new(closure.clone())?;
+            vm.charge(Fiber::SIZE);
+            Ok(Value::Fiber(Rc::new(RefCell::new(fiber))))
+        }
+        _ => Err("Argument to 'Fiber' must be a function.".to_string()),
+    }
+}
+
+/// Evaluates to the value the fiber next yields, or returns once it's done.
+fn resume(vm: &mut VM, args: &[Value]) -> NativeResult {
+    let fiber = expect_fiber("resume", &args[0])?;
+    vm.resume(fiber, args[1].clone())
+}
+
+fn is_done(_vm: &mut VM, args: &[Value]) -> NativeResult {
+    let fiber = expect_fiber("isDone", &args[0])?;
+    Ok(Value::Bool(fiber.borrow().is_done()))
+}
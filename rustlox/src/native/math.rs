@@ -0,0 +1,307 @@
+//! Math and numeric conversion natives, plus the VM's pseudo-random number
+//! generator.
+
+use super::{expect_number, expect_string, NativeResult};
+use crate::bigint::BigInt;
+use crate::number;
+use crate::value::Value;
+use crate::vm::VM;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::f64::consts;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn define(vm: &mut VM) {
+    vm.define_global("PI", Value::Number(consts::PI));
+    vm.define_global("E", Value::Number(consts::E));
+
+    vm.define_native("sqrt", 1, None, sqrt);
+    vm.define_native("pow", 2, None, pow);
+    vm.define_native("floor", 1, None, floor);
+    vm.define_native("ceil", 1, None, ceil);
+    vm.define_native("round", 1, None, round);
+    vm.define_native("abs", 1, None, abs);
+    vm.define_native("min", 2, None, min);
+    vm.define_native("max", 2, None, max);
+    vm.define_native("sin", 1, None, sin);
+    vm.define_native("cos", 1, None, cos);
+    vm.define_native("tan", 1, None, tan);
+    vm.define_native("asin", 1, None, asin);
+    vm.define_native("acos", 1, None, acos);
+    vm.define_native("atan", 1, None, atan);
+    vm.define_native("atan2", 2, None, atan2);
+    vm.define_native("log", 1, None, log);
+    vm.define_native("log10", 1, None, log10);
+    vm.define_native("exp", 1, None, exp);
+    vm.define_native("parseNumber", 1, None, parse_number);
+    vm.define_native("toString", 1, None, to_string);
+    vm.define_native("toInt", 1, None, to_int);
+    vm.define_native("toFloat", 1, None, to_float);
+    vm.define_native("bigint", 1, None, bigint);
+    vm.define_native("random", 0, None, random);
+    vm.define_native("randomInt", 2, None, random_int);
+    vm.define_native("seed", 1, None, seed);
+}
+
+/// xorshift64* seeded through splitmix64. Not cryptographically secure, but
+/// fast and the same seed always yields the same sequence on every platform.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // splitmix64 spreads small seeds across the whole state and never
+        // produces the all-zero state xorshift can't leave.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Rng {
+            state: if z == 0 { 1 } else { z },
+        }
+    }
+
+    pub fn from_time() -> Rng {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos() as u64);
+        Rng::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in `[0, bound)`.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        ((self.next_u64() as u128 * bound as u128) >> 64) as u64
+    }
+}
+
+fn expect_integer(name: &str, value: &Value) -> Result<i64, String> {
+    if let Value::Int(number) = value {
+        return Ok(*number);
+    }
+    let number = expect_number(name, value)?;
+    if number.fract() != 0.0 || !number.is_finite() {
+        return Err(format!("Argument to '{}' must be an integer.", name));
+    }
+    // 2^63 itself is the first float past i64::MAX.
+    if !(i64::MIN as f64..-(i64::MIN as f64)).contains(&number) {
+        return Err(format!("Argument to '{}' is out of range.", name));
+    }
+    Ok(number as i64)
+}
+
+macro_rules! unary {
+    ($name:ident, $lox_name:literal, $method:ident) => {
+        fn $name(_vm: &mut VM, args: &[Value]) -> NativeResult {
+            Ok(Value::Number(expect_number($lox_name, &args[0])?.$method()))
+        }
+    };
+}
+
+macro_rules! binary {
+    ($name:ident, $lox_name:literal, $method:ident) => {
+        fn $name(_vm: &mut VM, args: &[Value]) -> NativeResult {
+            let a = expect_number($lox_name, &args[0])?;
+            let b = expect_number($lox_name, &args[1])?;
+            Ok(Value::Number(a.$method(b)))
+        }
+    };
+}
+
+/// Like `unary!`, but ints and bigints are already whole, so they come back
+/// as they are rather than losing precision by going through a float.
+macro_rules! rounding {
+    ($name:ident, $lox_name:literal, $method:ident) => {
+        fn $name(_vm: &mut VM, args: &[Value]) -> NativeResult {
+            match &args[0] {
+                value @ (Value::Int(_) | Value::BigInt(_)) => Ok(value.clone()),
+                value => Ok(Value::Number(expect_number($lox_name, value)?.$method())),
+            }
+        }
+    };
+}
+
+/// Like `binary!`, but when both arguments are ints or bigints the one picked
+/// comes back as it is.
+macro_rules! picking {
+    ($name:ident, $lox_name:literal, $method:ident, $take_second:path) => {
+        fn $name(_vm: &mut VM, args: &[Value]) -> NativeResult {
+            let (a, b) = (&args[0], &args[1]);
+            if !is_integral(a) || !is_integral(b) {
+                let a = expect_number($lox_name, a)?;
+                let b = expect_number($lox_name, b)?;
+                return Ok(Value::Number(a.$method(b)));
+            }
+            match number::compare(a, b) {
+                Ok(Some($take_second)) => Ok(b.clone()),
+                _ => Ok(a.clone()),
+            }
+        }
+    };
+}
+
+fn is_integral(value: &Value) -> bool {
+    matches!(value, Value::Int(_) | Value::BigInt(_))
+}
+
+unary!(sqrt, "sqrt", sqrt);
+rounding!(floor, "floor", floor);
+rounding!(ceil, "ceil", ceil);
+// Halfway cases round away from zero, so round(-2.5) is -3.
+rounding!(round, "round", round);
+picking!(min, "min", min, Ordering::Greater);
+picking!(max, "max", max, Ordering::Less);
+unary!(sin, "sin", sin);
+unary!(cos, "cos", cos);
+unary!(tan, "tan", tan);
+unary!(asin, "asin", asin);
+unary!(acos, "acos", acos);
+unary!(atan, "atan", atan);
+binary!(atan2, "atan2", atan2);
+unary!(log, "log", ln);
+unary!(log10, "log10", log10);
+unary!(exp, "exp", exp);
+
+/// Ints and bigints keep their type.
+fn abs(vm: &mut VM, args: &[Value]) -> NativeResult {
+    match &args[0] {
+        Value::Int(int) => int
+            .checked_abs()
+            .map(Value::Int)
+            .ok_or_else(|| "Integer overflow.".to_string()),
+        Value::BigInt(int) if int.is_negative() => {
+            let int = int.negate();
+            vm.charge(int.size());
+            Ok(Value::BigInt(Rc::new(int)))
+        }
+        value @ Value::BigInt(_) => Ok(value.clone()),
+        value => Ok(Value::Number(expect_number("abs", value)?.abs())),
+    }
+}
+
+/// Follows `**`, so an int raised to a non-negative int stays an int.
+fn pow(vm: &mut VM, args: &[Value]) -> NativeResult {
+    expect_number("pow", &args[0])?;
+    expect_number("pow", &args[1])?;
+    let value = number::power(&args[0], &args[1]).map_err(|error| error.message.to_string())?;
+    if let Value::BigInt(int) = &value {
+        vm.charge(int.size());
+    }
+    Ok(value)
+}
+
+/// Accepts the same decimal syntax as number literals, optionally signed and
+/// with an exponent and `_` separators. Whole numbers without a fraction or
+/// exponent become ints. Anything else yields nil rather than an error, so
+/// scripts can validate input without aborting.
+fn parse_number(_vm: &mut VM, args: &[Value]) -> NativeResult {
+    let text = expect_string("parseNumber", &args[0])?.trim();
+    let valid = text
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E' | '_'));
+    let unsigned = text.strip_prefix(['-', '+']).unwrap_or(text);
+    // Only the syntax matters here; integers too large for a literal still
+    // parse below, as i64::MIN or as floats.
+    let well_formed = match number::literal(unsigned) {
+        Ok(_) => true,
+        Err(message) => message == "Integer literal is too large.",
+    };
+    if !valid || !well_formed {
+        return Ok(Value::Nil);
+    }
+    let text = text.replace('_', "");
+    Ok(match (text.parse::<i64>(), text.parse::<f64>()) {
+        (Ok(number), _) => Value::Int(number),
+        (_, Ok(number)) => Value::Number(number),
+        _ => Value::Nil,
+    })
+}
+
+fn to_string(vm: &mut VM, args: &[Value]) -> NativeResult {
+    match &args[0] {
+        Value::String(string) => Ok(Value::String(Rc::clone(string))),
+        value => {
+            let string = value.to_string();
+            vm.charge(string.len());
+            Ok(Value::String(Rc::from(string)))
+        }
+    }
+}
+
+/// Floats are truncated toward zero.
+fn to_int(_vm: &mut VM, args: &[Value]) -> NativeResult {
+    let value = match &args[0] {
+        Value::Int(value) => Some(*value),
+        Value::Number(value) if value.is_finite() => BigInt::from_f64(value.trunc())
+            .as_ref()
+            .and_then(BigInt::to_i64),
+        Value::Number(_) => None,
+        Value::BigInt(value) => value.to_i64(),
+        _ => return Err("Argument to 'toInt' must be a number.".to_string()),
+    };
+    match value {
+        Some(value) => Ok(Value::Int(value)),
+        None => Err(format!("{} is out of range for an int.", args[0])),
+    }
+}
+
+fn to_float(_vm: &mut VM, args: &[Value]) -> NativeResult {
+    Ok(Value::Number(expect_number("toFloat", &args[0])?))
+}
+
+/// Converts an int, a whole float or a string of decimal digits.
+fn bigint(vm: &mut VM, args: &[Value]) -> NativeResult {
+    let value = match &args[0] {
+        Value::BigInt(value) => return Ok(Value::BigInt(Rc::clone(value))),
+        Value::Int(value) => Some(BigInt::from_i64(*value)),
+        Value::Number(value) => BigInt::from_f64(*value),
+        Value::String(value) => BigInt::parse(value.trim()),
+        _ => return Err("Argument to 'bigint' must be a number or string.".to_string()),
+    };
+    match value {
+        Some(value) => {
+            vm.charge(value.size());
+            Ok(Value::BigInt(Rc::new(value)))
+        }
+        None => Err(format!("Cannot convert {} to a bigint.", args[0])),
+    }
+}
+
+fn random(vm: &mut VM, _args: &[Value]) -> NativeResult {
+    Ok(Value::Number(vm.rng().next_f64()))
+}
+
+/// Both bounds are inclusive.
+fn random_int(vm: &mut VM, args: &[Value]) -> NativeResult {
+    let low = expect_integer("randomInt", &args[0])?;
+    let high = expect_integer("randomInt", &args[1])?;
+    if low > high {
+        return Err("Lower bound of 'randomInt' must not exceed the upper bound.".to_string());
+    }
+    // The full i64 range spans 2^64 values, one more than fits in a u64.
+    let span = (high as i128 - low as i128 + 1) as u128;
+    let offset = match u64::try_from(span) {
+        Ok(span) => vm.rng().below(span),
+        Err(_) => vm.rng().next_u64(),
+    };
+    Ok(Value::Int((low as i128 + offset as i128) as i64))
+}
+
+fn seed(vm: &mut VM, args: &[Value]) -> NativeResult {
+    let seed = expect_integer("seed", &args[0])?;
+    *vm.rng() = Rng::new(seed as u64);
+    Ok(Value::Nil)
+}
@@ -0,0 +1,199 @@
+//! String natives. Lengths and indexes count Unicode scalar values, not bytes,
+//! so `len("héllo")` is 5 and every index lands on a character boundary.
+
+use super::{expect_number, expect_string, NativeResult};
+use crate::value::Value;
+use crate::vm::VM;
+use std::cell::RefCell;
+use std::mem;
+use std::rc::Rc;
+
+pub fn define(vm: &mut VM) {
+    vm.define_native("len", 1, None, len);
+    vm.define_native("charAt", 2, None, char_at);
+    vm.define_native("substring", 3, None, substring);
+    vm.define_native("indexOf", 2, None, index_of);
+    vm.define_native("contains", 2, None, contains);
+    vm.define_native("startsWith", 2, None, starts_with);
+    vm.define_native("endsWith", 2, None, ends_with);
+    vm.define_native("toUpper", 1, None, to_upper);
+    vm.define_native("toLower", 1, None, to_lower);
+    vm.define_native("trim", 1, None, trim);
+    vm.define_native("trimStart", 1, None, trim_start);
+    vm.define_native("trimEnd", 1, None, trim_end);
+    vm.define_native("replace", 3, None, replace);
+    vm.define_native("split", 2, None, split);
+    vm.define_native("join", 2, None, join);
+    vm.define_native("charCode", 2, None, char_code);
+    vm.define_native("fromCharCode", 1, None, from_char_code);
+}
+
+fn string(vm: &mut VM, value: impl Into<Rc<str>>) -> NativeResult {
+    let value = value.into();
+    vm.charge(value.len());
+    Ok(Value::String(value))
+}
+
+/// Checks that `value` is a whole number in `0..=max`. Substring ends may sit
+/// one past the last character, everything else must be strictly inside.
+fn expect_index(name: &str, value: &Value, max: usize) -> Result<usize, String> {
+    let index = expect_number(name, value)?;
+    if index.fract() != 0.0 {
+        return Err(format!("Index to '{}' must be an integer.", name));
+    }
+    if index < 0.0 || index > max as f64 {
+        return Err(format!("Index {} out of range for '{}'.", index, name));
+    }
+    Ok(index as usize)
+}
+
+/// Converts a character index into a byte offset into `string`.
+fn byte_offset(string: &str, index: usize) -> usize {
+    string
+        .char_indices()
+        .nth(index)
+        .map_or(string.len(), |(offset, _)| offset)
+}
+
+fn len(_vm: &mut VM, args: &[Value]) -> NativeResult {
+    match &args[0] {
+        Value::String(string) => Ok(Value::Int(string.chars().count() as i64)),
+        Value::List(list) => Ok(Value::Int(list.borrow().len() as i64)),
+        _ => Err("Argument to 'len' must be a string or list.".to_string()),
+    }
+}
+
+fn char_at(vm: &mut VM, args: &[Value]) -> NativeResult {
+    let value = expect_string("charAt", &args[0])?;
+    let length = value.chars().count();
+    if length == 0 {
+        return Err("Index 0 out of range for 'charAt'.".to_string());
+    }
+    let index = expect_index("charAt", &args[1], length - 1)?;
+    let character = value.chars().nth(index).unwrap_or_default();
+    string(vm, character.to_string())
+}
+
+fn substring(vm: &mut VM, args: &[Value]) -> NativeResult {
+    let value = expect_string("substring", &args[0])?;
+    let length = value.chars().count();
+    let start = expect_index("substring", &args[1], length)?;
+    let end = expect_index("substring", &args[2], length)?;
+    if start > end {
+        return Err("Start of 'substring' must not be after its end.".to_string());
+    }
+    string(
+        vm,
+        &value[byte_offset(value, start)..byte_offset(value, end)],
+    )
+}
+
+fn index_of(_vm: &mut VM, args: &[Value]) -> NativeResult {
+    let value = expect_string("indexOf", &args[0])?;
+    let needle = expect_string("indexOf", &args[1])?;
+    Ok(Value::Int(match value.find(needle) {
+        Some(offset) => value[..offset].chars().count() as i64,
+        None => -1,
+    }))
+}
+
+fn contains(_vm: &mut VM, args: &[Value]) -> NativeResult {
+    let value = expect_string("contains", &args[0])?;
+    let needle = expect_string("contains", &args[1])?;
+    Ok(Value::Bool(value.contains(needle)))
+}
+
+fn starts_with(_vm: &mut VM, args: &[Value]) -> NativeResult {
+    let value = expect_string("startsWith", &args[0])?;
+    let prefix = expect_string("startsWith", &args[1])?;
+    Ok(Value::Bool(value.starts_with(prefix)))
+}
+
+fn ends_with(_vm: &mut VM, args: &[Value]) -> NativeResult {
+    let value = expect_string("endsWith", &args[0])?;
+    let suffix = expect_string("endsWith", &args[1])?;
+    Ok(Value::Bool(value.ends_with(suffix)))
+}
+
+fn to_upper(vm: &mut VM, args: &[Value]) -> NativeResult {
+    string(vm, expect_string("toUpper", &args[0])?.to_uppercase())
+}
+
+fn to_lower(vm: &mut VM, args: &[Value]) -> NativeResult {
+    string(vm, expect_string("toLower", &args[0])?.to_lowercase())
+}
+
+fn trim(vm: &mut VM, args: &[Value]) -> NativeResult {
+    string(vm, expect_string("trim", &args[0])?.trim())
+}
+
+fn trim_start(vm: &mut VM, args: &[Value]) -> NativeResult {
+    string(vm, expect_string("trimStart", &args[0])?.trim_start())
+}
+
+fn trim_end(vm: &mut VM, args: &[Value]) -> NativeResult {
+    string(vm, expect_string("trimEnd", &args[0])?.trim_end())
+}
+
+fn replace(vm: &mut VM, args: &[Value]) -> NativeResult {
+    let value = expect_string("replace", &args[0])?;
+    let from = expect_string("replace", &args[1])?;
+    let to = expect_string("replace", &args[2])?;
+    if from.is_empty() {
+        return Err("Pattern to 'replace' must not be empty.".to_string());
+    }
+    string(vm, value.replace(from, to))
+}
+
+/// An empty separator splits the string into its characters.
+fn split(vm: &mut VM, args: &[Value]) -> NativeResult {
+    let value = expect_string("split", &args[0])?;
+    let separator = expect_string("split", &args[1])?;
+    let parts: Vec<Value> = if separator.is_empty() {
+        value
+            .chars()
+            .map(|c| Value::String(Rc::from(c.to_string())))
+            .collect()
+    } else {
+        value
+            .split(separator)
+            .map(|part| Value::String(Rc::from(part)))
+            .collect()
+    };
+    vm.charge(value.len() + parts.len() * mem::size_of::<Value>());
+    Ok(Value::List(Rc::new(RefCell::new(parts))))
+}
+
+fn join(vm: &mut VM, args: &[Value]) -> NativeResult {
+    let list = match &args[0] {
+        Value::List(list) => list,
+        _ => return Err("First argument to 'join' must be a list.".to_string()),
+    };
+    let separator = expect_string("join", &args[1])?;
+    let parts: Vec<String> = list.borrow().iter().map(Value::to_string).collect();
+    string(vm, parts.join(separator))
+}
+
+fn char_code(_vm: &mut VM, args: &[Value]) -> NativeResult {
+    let value = expect_string("charCode", &args[0])?;
+    let length = value.chars().count();
+    if length == 0 {
+        return Err("Index 0 out of range for 'charCode'.".to_string());
+    }
+    let index = expect_index("charCode", &args[1], length - 1)?;
+    let character = value.chars().nth(index).unwrap_or_default();
+    Ok(Value::Int(character as i64))
+}
+
+fn from_char_code(vm: &mut VM, args: &[Value]) -> NativeResult {
+    let code = expect_number("fromCharCode", &args[0])?;
+    let character = if code.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(&code) {
+        char::from_u32(code as u32)
+    } else {
+        None
+    };
+    match character {
+        Some(character) => string(vm, character.to_string()),
+        None => Err(format!("Invalid character code {}.", code)),
+    }
+}
@@ -0,0 +1,385 @@
+//! Arithmetic on the numeric value types.
+//!
+//! Two ints stay an int, and overflow is an error rather than wrapping. As
+//! soon as a float is involved the int is promoted and the result is a float.
+//! `/` always produces a float; `~/` is the integer division.
+//!
+//! Bigints absorb ints, so `1n + 1` is `2n`, but refuse to mix with floats
+//! since that would silently lose precision. Both `/` and `~/` truncate
+//! bigints.
+//!
+//! Number literals are parsed here too, since which value type a literal
+//! produces follows the same rules.
+
+use crate::bigint::BigInt;
+use crate::error::RuntimeErrorKind;
+use crate::value::Value;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+/// Caps bigint `*` and `**` so a typo can't allocate gigabytes.
+const MAX_BIGINT_BITS: u64 = 1 << 24;
+
+pub struct Error {
+    pub kind: RuntimeErrorKind,
+    pub message: &'static str,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+const NUMBERS: Error = Error {
+    kind: RuntimeErrorKind::Type,
+    message: "Operands must be numbers.",
+};
+/// `+` also concatenates strings, which the VM handles before it gets here.
+const NUMBERS_OR_STRINGS: Error = Error {
+    kind: RuntimeErrorKind::Type,
+    message: "Operands must be two numbers or two strings.",
+};
+const INTEGERS: Error = Error {
+    kind: RuntimeErrorKind::Type,
+    message: "Operands must be integers.",
+};
+const OVERFLOW: Error = Error {
+    kind: RuntimeErrorKind::Arithmetic,
+    message: "Integer overflow.",
+};
+const DIVISION_BY_ZERO: Error = Error {
+    kind: RuntimeErrorKind::Arithmetic,
+    message: "Division by zero.",
+};
+const TOO_LARGE: Error = Error {
+    kind: RuntimeErrorKind::Arithmetic,
+    message: "Bigint result is too large.",
+};
+
+enum Pair<'v> {
+    Int(i64, i64),
+    Float(f64, f64),
+    Big(Cow<'v, BigInt>, Cow<'v, BigInt>),
+}
+
+fn big(value: &Value) -> Option<Cow<'_, BigInt>> {
+    match value {
+        Value::BigInt(value) => Some(Cow::Borrowed(&**value)),
+        Value::Int(value) => Some(Cow::Owned(BigInt::from_i64(*value))),
+        _ => None,
+    }
+}
+
+fn pair<'v>(a: &'v Value, b: &'v Value) -> Result<Pair<'v>> {
+    match (a, b) {
+        (Value::Int(a), Value::Int(b)) => Ok(Pair::Int(*a, *b)),
+        (Value::Int(a), Value::Number(b)) => Ok(Pair::Float(*a as f64, *b)),
+        (Value::Number(a), Value::Int(b)) => Ok(Pair::Float(*a, *b as f64)),
+        (Value::Number(a), Value::Number(b)) => Ok(Pair::Float(*a, *b)),
+        (Value::BigInt(_), Value::Number(_)) | (Value::Number(_), Value::BigInt(_)) => Err(Error {
+            kind: RuntimeErrorKind::Type,
+            message: "Cannot mix bigints and floats.",
+        }),
+        _ => match (big(a), big(b)) {
+            (Some(a), Some(b)) => Ok(Pair::Big(a, b)),
+            _ => Err(NUMBERS),
+        },
+    }
+}
+
+fn is_number(value: &Value) -> bool {
+    matches!(value, Value::Int(_) | Value::Number(_) | Value::BigInt(_))
+}
+
+fn bigint(value: BigInt) -> Result<Value> {
+    Ok(Value::BigInt(Rc::new(value)))
+}
+
+fn big_div_rem(a: &BigInt, b: &BigInt) -> Result<(BigInt, BigInt)> {
+    a.div_rem(b).ok_or(DIVISION_BY_ZERO)
+}
+
+fn ints(a: &Value, b: &Value) -> Result<(i64, i64)> {
+    match (a, b) {
+        (Value::Int(a), Value::Int(b)) => Ok((*a, *b)),
+        _ => Err(INTEGERS),
+    }
+}
+
+fn checked(value: Option<i64>) -> Result<Value> {
+    value.map(Value::Int).ok_or(OVERFLOW)
+}
+
+/// Parses a number literal: decimal with an optional fraction and exponent,
+/// or a `0x`, `0o` or `0b` integer, with `_` allowed between digits. Literals
+/// with a fraction or exponent are floats, and an `n` suffix makes an integer
+/// a bigint.
+pub fn literal(lexeme: &str) -> std::result::Result<Value, &'static str> {
+    let (text, big) = match lexeme.strip_suffix('n') {
+        Some(text) => (text, true),
+        None => (lexeme, false),
+    };
+
+    let radix = match text.get(..2) {
+        Some("0x" | "0X") => 16,
+        Some("0o" | "0O") => 8,
+        Some("0b" | "0B") => 2,
+        _ => 10,
+    };
+    if radix != 10 {
+        let digits = digits(&text[2..], radix)?;
+        if big {
+            return Ok(Value::BigInt(Rc::new(radix_bigint(&digits, radix))));
+        }
+        return i64::from_str_radix(&digits, radix)
+            .map(Value::Int)
+            .map_err(|_| "Integer literal is too large.");
+    }
+
+    let (mantissa, exponent) = match text.find(['e', 'E']) {
+        Some(at) => (&text[..at], Some(&text[at + 1..])),
+        None => (text, None),
+    };
+    let (whole, fraction) = match mantissa.find('.') {
+        Some(at) => (&mantissa[..at], Some(&mantissa[at + 1..])),
+        None => (mantissa, None),
+    };
+    let whole = digits(whole, 10)?;
+
+    if fraction.is_none() && exponent.is_none() {
+        if big {
+            let value = BigInt::parse(&whole).ok_or("Invalid digit in number literal.")?;
+            return Ok(Value::BigInt(Rc::new(value)));
+        }
+        return whole
+            .parse()
+            .map(Value::Int)
+            .map_err(|_| "Integer literal is too large.");
+    }
+
+    if big {
+        return Err("Bigint literal must be an integer.");
+    }
+    let mut float = whole;
+    if let Some(fraction) = fraction {
+        float.push('.');
+        float.push_str(&digits(fraction, 10)?);
+    }
+    if let Some(exponent) = exponent {
+        let (sign, exponent) = match exponent.strip_prefix(['+', '-']) {
+            Some(rest) => (&exponent[..1], rest),
+            None => ("", exponent),
+        };
+        if exponent.is_empty() {
+            return Err("Expect digits in exponent.");
+        }
+        float.push('e');
+        float.push_str(sign);
+        float.push_str(&digits(exponent, 10)?);
+    }
+
+    match float.parse::<f64>() {
+        Ok(value) if value.is_finite() => Ok(Value::Number(value)),
+        _ => Err("Number literal is too large."),
+    }
+}
+
+/// Checks the digits of one part of a literal and drops its separators.
+fn digits(text: &str, radix: u32) -> std::result::Result<String, &'static str> {
+    if text.is_empty() {
+        return Err("Expect digits after number prefix.");
+    }
+    if text.starts_with('_') || text.ends_with('_') || text.contains("__") {
+        return Err("Digit separators must sit between digits.");
+    }
+    let digits = text.replace('_', "");
+    if !digits.chars().all(|c| c.is_digit(radix)) {
+        return Err("Invalid digit in number literal.");
+    }
+    Ok(digits)
+}
+
+fn radix_bigint(digits: &str, radix: u32) -> BigInt {
+    let base = BigInt::from_i64(radix as i64);
+    digits.chars().fold(BigInt::zero(), |value, c| {
+        let digit = c.to_digit(radix).unwrap_or_default();
+        value.multiply(&base).add(&BigInt::from_i64(digit as i64))
+    })
+}
+
+pub fn add(a: &Value, b: &Value) -> Result<Value> {
+    if !is_number(a) || !is_number(b) {
+        return Err(NUMBERS_OR_STRINGS);
+    }
+    match pair(a, b)? {
+        Pair::Int(a, b) => checked(a.checked_add(b)),
+        Pair::Float(a, b) => Ok(Value::Number(a + b)),
+        Pair::Big(a, b) => bigint(a.add(&b)),
+    }
+}
+
+pub fn subtract(a: &Value, b: &Value) -> Result<Value> {
+    match pair(a, b)? {
+        Pair::Int(a, b) => checked(a.checked_sub(b)),
+        Pair::Float(a, b) => Ok(Value::Number(a - b)),
+        Pair::Big(a, b) => bigint(a.subtract(&b)),
+    }
+}
+
+pub fn multiply(a: &Value, b: &Value) -> Result<Value> {
+    match pair(a, b)? {
+        Pair::Int(a, b) => checked(a.checked_mul(b)),
+        Pair::Float(a, b) => Ok(Value::Number(a * b)),
+        Pair::Big(a, b) if a.bits() + b.bits() > MAX_BIGINT_BITS => Err(TOO_LARGE),
+        Pair::Big(a, b) => bigint(a.multiply(&b)),
+    }
+}
+
+pub fn divide(a: &Value, b: &Value) -> Result<Value> {
+    match pair(a, b)? {
+        Pair::Int(a, b) => Ok(Value::Number(a as f64 / b as f64)),
+        Pair::Float(a, b) => Ok(Value::Number(a / b)),
+        Pair::Big(a, b) => bigint(big_div_rem(&a, &b)?.0),
+    }
+}
+
+/// The remainder takes the sign of the dividend: -7 % 3 is -1 and 7 % -3 is
+/// 1.
+pub fn modulo(a: &Value, b: &Value) -> Result<Value> {
+    match pair(a, b)? {
+        Pair::Int(_, 0) => Err(DIVISION_BY_ZERO),
+        // i64::MIN % -1 is 0; only the intermediate quotient overflows.
+        Pair::Int(a, b) => Ok(Value::Int(a.wrapping_rem(b))),
+        Pair::Float(a, b) => Ok(Value::Number(a % b)),
+        Pair::Big(a, b) => bigint(big_div_rem(&a, &b)?.1),
+    }
+}
+
+/// Truncates toward zero, so a == (a ~/ b) * b + a % b.
+pub fn int_divide(a: &Value, b: &Value) -> Result<Value> {
+    match pair(a, b)? {
+        Pair::Int(_, 0) => Err(DIVISION_BY_ZERO),
+        Pair::Int(a, b) => checked(a.checked_div(b)),
+        Pair::Float(a, b) => Ok(Value::Number((a / b).trunc())),
+        Pair::Big(a, b) => bigint(big_div_rem(&a, &b)?.0),
+    }
+}
+
+/// A negative int exponent can't produce an int, so it promotes to float.
+pub fn power(a: &Value, b: &Value) -> Result<Value> {
+    match pair(a, b)? {
+        Pair::Int(a, b) if b >= 0 => checked(u32::try_from(b).ok().and_then(|b| a.checked_pow(b))),
+        Pair::Int(a, b) => Ok(Value::Number((a as f64).powf(b as f64))),
+        Pair::Float(a, b) => Ok(Value::Number(a.powf(b))),
+        Pair::Big(a, b) => {
+            if b.is_negative() {
+                return Err(Error {
+                    kind: RuntimeErrorKind::Arithmetic,
+                    message: "Bigint exponent must not be negative.",
+                });
+            }
+            let exponent = match b.to_i64().and_then(|b| u32::try_from(b).ok()) {
+                Some(exponent) => exponent,
+                // Only 0, 1 and -1 survive a huge exponent, and for those
+                // just its parity matters.
+                None if a.bits() <= 1 => {
+                    let odd = !big_div_rem(&b, &BigInt::from_i64(2))?.1.is_zero();
+                    2 + odd as u32
+                }
+                None => return Err(TOO_LARGE),
+            };
+            if a.bits().saturating_mul(exponent as u64) > MAX_BIGINT_BITS {
+                return Err(TOO_LARGE);
+            }
+            bigint(a.pow(exponent))
+        }
+    }
+}
+
+pub fn negate(a: &Value) -> Result<Value> {
+    match a {
+        Value::Int(a) => checked(a.checked_neg()),
+        Value::Number(a) => Ok(Value::Number(-a)),
+        Value::BigInt(a) => bigint(a.negate()),
+        _ => Err(Error {
+            kind: RuntimeErrorKind::Type,
+            message: "Operand must be a number.",
+        }),
+    }
+}
+
+/// None when either side is NaN.
+pub fn compare(a: &Value, b: &Value) -> Result<Option<Ordering>> {
+    match pair(a, b)? {
+        Pair::Int(a, b) => Ok(Some(a.cmp(&b))),
+        Pair::Float(a, b) => Ok(a.partial_cmp(&b)),
+        Pair::Big(a, b) => Ok(Some(a.cmp(&b))),
+    }
+}
+
+/// Bigints equal ints and floats with the same value, so 1n == 1 == 1.0.
+pub fn big_equals(a: &BigInt, b: &Value) -> bool {
+    match b {
+        Value::Int(b) => *a == BigInt::from_i64(*b),
+        Value::Number(b) => BigInt::from_f64(*b).is_some_and(|b| *a == b),
+        _ => false,
+    }
+}
+
+/// Ints and floats are equal when they denote the same number, so 1 == 1.0.
+/// The comparison is exact: 2^53 + 1 is not equal to the float 2^53.
+pub fn int_equals_float(a: i64, b: f64) -> bool {
+    b.fract() == 0.0 && b >= i64::MIN as f64 && b < i64::MAX as f64 && b as i64 == a
+}
+
+pub fn bit_and(a: &Value, b: &Value) -> Result<Value> {
+    let (a, b) = ints(a, b)?;
+    Ok(Value::Int(a & b))
+}
+
+pub fn bit_or(a: &Value, b: &Value) -> Result<Value> {
+    let (a, b) = ints(a, b)?;
+    Ok(Value::Int(a | b))
+}
+
+pub fn bit_xor(a: &Value, b: &Value) -> Result<Value> {
+    let (a, b) = ints(a, b)?;
+    Ok(Value::Int(a ^ b))
+}
+
+pub fn bit_not(a: &Value) -> Result<Value> {
+    match a {
+        Value::Int(a) => Ok(Value::Int(!a)),
+        _ => Err(Error {
+            kind: RuntimeErrorKind::Type,
+            message: "Operand must be an integer.",
+        }),
+    }
+}
+
+fn shift_amount(b: i64) -> Result<u32> {
+    match b {
+        0..=63 => Ok(b as u32),
+        _ => Err(Error {
+            kind: RuntimeErrorKind::Arithmetic,
+            message: "Shift amount must be between 0 and 63.",
+        }),
+    }
+}
+
+/// Shifting significant bits out of the top is an overflow, like any other
+/// int arithmetic.
+pub fn shift_left(a: &Value, b: &Value) -> Result<Value> {
+    let (a, b) = ints(a, b)?;
+    let b = shift_amount(b)?;
+    let shifted = a << b;
+    if shifted >> b != a {
+        return Err(OVERFLOW);
+    }
+    Ok(Value::Int(shifted))
+}
+
+/// Arithmetic shift: the sign bit is copied in from the left.
+pub fn shift_right(a: &Value, b: &Value) -> Result<Value> {
+    let (a, b) = ints(a, b)?;
+    Ok(Value::Int(a >> shift_amount(b)?))
+}
@@ -1,23 +1,31 @@
+use crate::error::{Diagnostic, Location};
 use crate::expr::{self, Expr};
-use crate::scanner::*;
+use crate::scanner::{self, *};
 use crate::stmt::{self, FunctionKind, Stmt};
 
 #[derive(Copy, Clone, PartialEq)]
 enum Loop {
-    None,
     While,
     For,
+    /// `break` may leave a `match`, but `continue` passes it by.
+    Match,
+}
+
+/// A statement enclosing the one being parsed that `break` can leave.
+struct LoopContext<'a> {
+    kind: Loop,
+    label: Option<&'a Token<'a>>,
 }
 
 struct Parser<'a> {
     tokens: &'a Vec<Token<'a>>,
     current: usize,
     last_line: i32,
-    had_error: bool,
+    errors: Vec<Diagnostic>,
     panic_mode: bool,
 
     function_kind: FunctionKind,
-    loop_kind: Loop,
+    loops: Vec<LoopContext<'a>>,
 }
 
 type ParseResult<T> = std::result::Result<T, ()>;
@@ -25,13 +33,13 @@ type ParseResult<T> = std::result::Result<T, ()>;
 impl<'a> Parser<'a> {
     fn new(tokens: &'a Vec<Token<'a>>) -> Parser<'a> {
         Parser {
-            tokens: tokens,
+            tokens,
             current: 0,
             last_line: tokens.last().unwrap().line,
-            had_error: false,
+            errors: Vec::new(),
             panic_mode: false,
             function_kind: FunctionKind::Script,
-            loop_kind: Loop::None,
+            loops: Vec::new(),
         }
     }
 
@@ -55,10 +63,11 @@ impl<'a> Parser<'a> {
     }
 
     fn check(&self, desired: TokenKind) -> bool {
-        match self.peek() {
-            Some(Token { kind, .. }) if *kind == desired => true,
-            _ => false,
-        }
+        matches!(self.peek(), Some(Token { kind, .. }) if *kind == desired)
+    }
+
+    fn check_next(&self, desired: TokenKind) -> bool {
+        matches!(self.tokens.get(self.current + 1), Some(Token { kind, .. }) if *kind == desired)
     }
 
     fn match_current(&mut self, kind: TokenKind) -> bool {
@@ -81,17 +90,19 @@ impl<'a> Parser<'a> {
             self.last_line
         };
 
-        eprint!("[line {}] Error", line);
-
-        if token.is_none() {
-            eprint!(" at end");
-        } else if token.unwrap().kind != TokenKind::Error {
-            eprint!(" at '{}'", token.unwrap().lexeme);
-        }
+        // Error tokens carry the scanner's message in place of a lexeme.
+        let (location, message) = match token {
+            None => (Location::End, message),
+            Some(t) if t.kind == TokenKind::Error => (Location::Unknown, t.lexeme),
+            Some(t) => (Location::Token(t.lexeme.to_string()), message),
+        };
 
-        eprintln!(": {}", message);
+        self.errors.push(Diagnostic {
+            line,
+            location,
+            message: message.to_string(),
+        });
         self.panic_mode = true;
-        self.had_error = true;
     }
 
     fn consume(&mut self, kind: TokenKind, message: &'static str) -> ParseResult<&'a Token<'a>> {
@@ -105,26 +116,42 @@ impl<'a> Parser<'a> {
     }
 
     fn declaration(&mut self) -> ParseResult<Stmt<'a>> {
-        if self.match_current(TokenKind::Fun) {
+        if self.check(TokenKind::Fun) && !self.check_next(TokenKind::LeftParen) {
+            self.advance();
             return self.function(FunctionKind::Function);
         }
 
         if self.match_current(TokenKind::Var) {
-            return self.var_declaration();
+            return self.var_declaration(false);
+        }
+        if self.match_current(TokenKind::Const) {
+            return self.var_declaration(true);
         }
 
         self.statement()
     }
 
     fn function(&mut self, kind: FunctionKind) -> ParseResult<Stmt<'a>> {
-        let enclosing_kind = self.function_kind;
-        self.function_kind = kind;
-
         let name = self.consume(TokenKind::Identifier, "Expect function name.")?;
 
         self.consume(TokenKind::LeftParen, "Expect '(' after function name")?;
+        let (params, rest) = self.parameters()?;
+        self.consume(TokenKind::LeftBrace, "Expect '{' before function body.")?;
+        let body = self.function_body(kind)?;
 
-        let mut params: Vec<&'a Token<'a>> = Vec::new();
+        Ok(Stmt::Function(stmt::Function {
+            name,
+            params,
+            rest,
+            body,
+            brace: self.previous().unwrap(),
+        }))
+    }
+
+    /// A rest parameter, if any, comes last and is returned separately.
+    fn parameters(&mut self) -> ParseResult<(Vec<stmt::Param<'a>>, Option<&'a Token<'a>>)> {
+        let mut params: Vec<stmt::Param<'a>> = Vec::new();
+        let mut rest = None;
 
         if !self.check(TokenKind::RightParen) {
             loop {
@@ -132,7 +159,25 @@ impl<'a> Parser<'a> {
                     self.error(self.peek(), "Can't have more than 255 parameters.");
                 }
 
-                params.push(self.consume(TokenKind::Identifier, "Expect parameter name.")?);
+                if self.match_current(TokenKind::DotDotDot) {
+                    rest = Some(self.consume(TokenKind::Identifier, "Expect parameter name.")?);
+                    self.consume(TokenKind::RightParen, "Expect ')' after rest parameter.")?;
+                    return Ok((params, rest));
+                }
+
+                let name = self.consume(TokenKind::Identifier, "Expect parameter name.")?;
+                let default = if self.match_current(TokenKind::Equal) {
+                    Some(self.expression()?)
+                } else {
+                    if params.last().is_some_and(|param| param.default.is_some()) {
+                        self.error(
+                            Some(name),
+                            "Parameter without a default can't follow one with a default.",
+                        );
+                    }
+                    None
+                };
+                params.push(stmt::Param { name, default });
 
                 if !self.match_current(TokenKind::Comma) {
                     break;
@@ -141,28 +186,37 @@ impl<'a> Parser<'a> {
         }
 
         self.consume(TokenKind::RightParen, "Expect ')' after parameters.")?;
-        self.consume(TokenKind::LeftBrace, "Expect '{' before function body.")?;
+        Ok((params, rest))
+    }
 
-        let body = self.block()?;
+    fn function_body(&mut self, kind: FunctionKind) -> ParseResult<Vec<Stmt<'a>>> {
+        let enclosing_kind = self.function_kind;
+        let enclosing_loops = std::mem::take(&mut self.loops);
+        self.function_kind = kind;
 
-        self.function_kind = enclosing_kind;
+        let body = self.block();
 
-        Ok(Stmt::Function(stmt::Function {
-            name,
-            params,
-            body,
-            kind,
-            brace: self.previous().unwrap(),
-        }))
+        self.function_kind = enclosing_kind;
+        self.loops = enclosing_loops;
+        body
     }
 
     fn statement(&mut self) -> ParseResult<Stmt<'a>> {
+        if self.check(TokenKind::Identifier) && self.check_next(TokenKind::Colon) {
+            return self.labeled_statement();
+        }
+        if self.match_current(TokenKind::Do) {
+            return self.do_while_statement(None);
+        }
         if self.match_current(TokenKind::For) {
-            return self.for_statement();
+            return self.for_statement(None);
         }
         if self.match_current(TokenKind::If) {
             return self.if_statement();
         }
+        if self.match_current(TokenKind::Match) {
+            return self.match_statement();
+        }
         if self.match_current(TokenKind::Print) {
             return self.print_statement();
         }
@@ -170,7 +224,7 @@ impl<'a> Parser<'a> {
             return self.return_statement();
         }
         if self.match_current(TokenKind::While) {
-            return self.while_statement();
+            return self.while_statement(None);
         }
         if self.match_current(TokenKind::LeftBrace) {
             return self.block_statement();
@@ -184,11 +238,14 @@ impl<'a> Parser<'a> {
         self.expression_statement()
     }
 
-    fn var_declaration(&mut self) -> ParseResult<Stmt<'a>> {
+    fn var_declaration(&mut self, constant: bool) -> ParseResult<Stmt<'a>> {
         let name = self.consume(TokenKind::Identifier, "Expect variable name.")?;
 
         let initializer = if self.match_current(TokenKind::Equal) {
             Some(self.expression()?)
+        } else if constant {
+            self.error(self.peek(), "Expect '=' after constant name.");
+            return Err(());
         } else {
             None
         };
@@ -197,16 +254,72 @@ impl<'a> Parser<'a> {
             TokenKind::Semicolon,
             "Expect ';' after variable declaration.",
         )?;
-        Ok(Stmt::Var(stmt::Var { name, initializer }))
+        Ok(Stmt::Var(stmt::Var {
+            name,
+            initializer,
+            constant,
+        }))
     }
 
-    fn for_statement(&mut self) -> ParseResult<Stmt<'a>> {
+    fn labeled_statement(&mut self) -> ParseResult<Stmt<'a>> {
+        let label = self.advance();
+        self.advance();
+        let shadows = self.loops.iter().any(|context| {
+            context
+                .label
+                .is_some_and(|outer| outer.lexeme == label.lexeme)
+        });
+        if shadows {
+            self.error(Some(label), "Already an enclosing loop with this label.");
+        }
+
+        if self.match_current(TokenKind::Do) {
+            return self.do_while_statement(Some(label));
+        }
+        if self.match_current(TokenKind::For) {
+            return self.for_statement(Some(label));
+        }
+        if self.match_current(TokenKind::While) {
+            return self.while_statement(Some(label));
+        }
+        self.error(self.peek(), "Expect loop after label.");
+        Err(())
+    }
+
+    fn loop_body(&mut self, kind: Loop, label: Option<&'a Token<'a>>) -> ParseResult<Stmt<'a>> {
+        self.loops.push(LoopContext { kind, label });
+        let body = self.statement();
+        self.loops.pop();
+        body
+    }
+
+    fn do_while_statement(&mut self, label: Option<&'a Token<'a>>) -> ParseResult<Stmt<'a>> {
+        let body = Box::from(self.loop_body(Loop::While, label)?);
+
+        self.consume(TokenKind::While, "Expect 'while' after do-while body.")?;
+        self.consume(TokenKind::LeftParen, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenKind::RightParen, "Expect ')' after condition.")?;
+        let end = self.consume(TokenKind::Semicolon, "Expect ';' after do-while condition.")?;
+
+        Ok(Stmt::DoWhile(stmt::DoWhile {
+            label,
+            body,
+            condition,
+            end,
+        }))
+    }
+
+    fn for_statement(&mut self, label: Option<&'a Token<'a>>) -> ParseResult<Stmt<'a>> {
         self.consume(TokenKind::LeftParen, "Expect '(' after 'for'.")?;
+        if self.check(TokenKind::Identifier) && self.check_next(TokenKind::In) {
+            return self.for_in_statement(label);
+        }
 
         let initializer = if self.match_current(TokenKind::Semicolon) {
             None
         } else if self.match_current(TokenKind::Var) {
-            Some(self.var_declaration()?)
+            Some(self.var_declaration(false)?)
         } else {
             Some(self.expression_statement()?)
         };
@@ -223,18 +336,36 @@ impl<'a> Parser<'a> {
         } else {
             None
         };
-        self.consume(TokenKind::RightParen, "Expect ')' after for clauses")?;
+        let paren = self.consume(TokenKind::RightParen, "Expect ')' after for clauses")?;
 
-        let enclosing_loop = self.loop_kind;
-        self.loop_kind = Loop::For;
-        let body = Box::from(self.statement()?);
-        self.loop_kind = enclosing_loop;
+        let body = Box::from(self.loop_body(Loop::For, label)?);
 
         Ok(Stmt::For(stmt::For {
-            initializer: initializer.map(|stmt| Box::from(stmt)),
+            label,
+            initializer: initializer.map(Box::from),
             condition,
             increment,
+            paren,
+            body,
+            end: self.previous().unwrap(),
+        }))
+    }
+
+    fn for_in_statement(&mut self, label: Option<&'a Token<'a>>) -> ParseResult<Stmt<'a>> {
+        let name = self.advance();
+        let keyword = self.advance();
+        let iterable = self.expression()?;
+        self.consume(TokenKind::RightParen, "Expect ')' after for-in iterable.")?;
+
+        let body = Box::from(self.loop_body(Loop::For, label)?);
+
+        Ok(Stmt::ForIn(stmt::ForIn {
+            label,
+            name,
+            keyword,
+            iterable,
             body,
+            end: self.previous().unwrap(),
         }))
     }
 
@@ -244,6 +375,7 @@ impl<'a> Parser<'a> {
         self.consume(TokenKind::RightParen, "Expect ')' after condition.")?;
 
         let then_branch = Box::from(self.statement()?);
+        let then_end = self.previous().unwrap();
         let else_branch = if self.match_current(TokenKind::Else) {
             Some(Box::from(self.statement()?))
         } else {
@@ -253,10 +385,109 @@ impl<'a> Parser<'a> {
         Ok(Stmt::If(stmt::If {
             condition,
             then_branch,
+            then_end,
             else_branch,
+            end: self.previous().unwrap(),
         }))
     }
 
+    fn match_statement(&mut self) -> ParseResult<Stmt<'a>> {
+        let keyword = self.previous().unwrap();
+        self.consume(TokenKind::LeftParen, "Expect '(' after 'match'.")?;
+        let subject = self.expression()?;
+        self.consume(TokenKind::RightParen, "Expect ')' after match value.")?;
+        self.consume(TokenKind::LeftBrace, "Expect '{' before match cases.")?;
+
+        self.loops.push(LoopContext {
+            kind: Loop::Match,
+            label: None,
+        });
+
+        let mut cases = Vec::new();
+        let mut default = None;
+        while !self.is_at_end() && !self.check(TokenKind::RightBrace) {
+            if default.is_some() {
+                self.error(self.peek(), "'default' must be the last case.");
+            }
+
+            if self.match_current(TokenKind::Case) {
+                let keyword = self.previous().unwrap();
+                let mut patterns = vec![self.pattern()?];
+                while self.match_current(TokenKind::Comma) {
+                    patterns.push(self.pattern()?);
+                }
+                let guard = if self.match_current(TokenKind::If) {
+                    Some(self.expression()?)
+                } else {
+                    None
+                };
+                self.consume(TokenKind::Colon, "Expect ':' after case.")?;
+                cases.push(stmt::Case {
+                    keyword,
+                    patterns,
+                    guard,
+                    body: self.case_body()?,
+                });
+            } else if self.match_current(TokenKind::Default) {
+                self.consume(TokenKind::Colon, "Expect ':' after 'default'.")?;
+                default = Some(self.case_body()?);
+            } else {
+                self.error(self.peek(), "Expect 'case' or 'default'.");
+                return Err(());
+            }
+        }
+
+        self.loops.pop();
+        let brace = self.consume(TokenKind::RightBrace, "Expect '}' after match cases.")?;
+        Ok(Stmt::Match(stmt::Match {
+            keyword,
+            subject,
+            cases,
+            default,
+            brace,
+        }))
+    }
+
+    fn pattern(&mut self) -> ParseResult<stmt::Pattern<'a>> {
+        if matches!(
+            self.peek(),
+            Some(Token {
+                kind: TokenKind::Identifier,
+                lexeme: "_",
+                ..
+            })
+        ) {
+            self.advance();
+            return Ok(stmt::Pattern::Wildcard);
+        }
+
+        let start = self.or()?;
+        if self.match_current(TokenKind::DotDot) || self.match_current(TokenKind::DotDotEqual) {
+            let inclusive = self.previous().unwrap().kind == TokenKind::DotDotEqual;
+            let end = self.or()?;
+            return Ok(stmt::Pattern::Range {
+                start,
+                end,
+                inclusive,
+            });
+        }
+
+        Ok(stmt::Pattern::Value(start))
+    }
+
+    /// Cases don't fall through, so a body simply runs until the next case.
+    fn case_body(&mut self) -> ParseResult<Vec<Stmt<'a>>> {
+        let mut statements = Vec::new();
+        while !self.is_at_end()
+            && !self.check(TokenKind::Case)
+            && !self.check(TokenKind::Default)
+            && !self.check(TokenKind::RightBrace)
+        {
+            statements.push(self.declaration()?);
+        }
+        Ok(statements)
+    }
+
     fn print_statement(&mut self) -> ParseResult<Stmt<'a>> {
         let keyword = self.previous().unwrap();
         let expr = self.expression()?;
@@ -281,17 +512,19 @@ impl<'a> Parser<'a> {
         Ok(Stmt::Return(stmt::Return { keyword, value }))
     }
 
-    fn while_statement(&mut self) -> ParseResult<Stmt<'a>> {
+    fn while_statement(&mut self, label: Option<&'a Token<'a>>) -> ParseResult<Stmt<'a>> {
         self.consume(TokenKind::LeftParen, "Expect '(' after 'while'.")?;
         let condition = self.expression()?;
         self.consume(TokenKind::RightParen, "Expect ')' after condition.")?;
 
-        let enclosing_loop = self.loop_kind;
-        self.loop_kind = Loop::While;
-        let body = Box::from(self.statement()?);
-        self.loop_kind = enclosing_loop;
+        let body = Box::from(self.loop_body(Loop::While, label)?);
 
-        Ok(Stmt::While(stmt::While { condition, body }))
+        Ok(Stmt::While(stmt::While {
+            label,
+            condition,
+            body,
+            end: self.previous().unwrap(),
+        }))
     }
 
     fn block(&mut self) -> ParseResult<Vec<Stmt<'a>>> {
@@ -313,23 +546,41 @@ impl<'a> Parser<'a> {
     }
 
     fn break_statement(&mut self) -> ParseResult<Stmt<'a>> {
-        if self.loop_kind == Loop::None {
-            self.error(self.previous(), "Unexpected 'break' statement.");
+        let keyword = self.previous().unwrap();
+        if self.loops.is_empty() {
+            self.error(Some(keyword), "Unexpected 'break' statement.");
         }
+        let label = self.jump_label();
         self.consume(TokenKind::Semicolon, "Expect ';' after 'break'.")?;
-        Ok(Stmt::Break(stmt::Break {
-            keyword: self.previous().unwrap(),
-        }))
+        Ok(Stmt::Break(stmt::Break { keyword, label }))
     }
 
     fn continue_statement(&mut self) -> ParseResult<Stmt<'a>> {
-        if self.loop_kind == Loop::None {
-            self.error(self.previous(), "Unexpected 'continue' statement.");
+        let keyword = self.previous().unwrap();
+        if self.loops.iter().all(|context| context.kind == Loop::Match) {
+            self.error(Some(keyword), "Unexpected 'continue' statement.");
         }
+        let label = self.jump_label();
         self.consume(TokenKind::Semicolon, "Expect ';' after 'continue'.")?;
-        Ok(Stmt::Continue(stmt::Continue {
-            keyword: self.previous().unwrap(),
-        }))
+        Ok(Stmt::Continue(stmt::Continue { keyword, label }))
+    }
+
+    /// The optional label after `break` or `continue`, which must name an
+    /// enclosing loop.
+    fn jump_label(&mut self) -> Option<&'a Token<'a>> {
+        if !self.match_current(TokenKind::Identifier) {
+            return None;
+        }
+        let label = self.previous().unwrap();
+        let known = self.loops.iter().any(|context| {
+            context
+                .label
+                .is_some_and(|outer| outer.lexeme == label.lexeme)
+        });
+        if !known {
+            self.error(Some(label), "No enclosing loop with this label.");
+        }
+        Some(label)
     }
 
     fn expression_statement(&mut self) -> ParseResult<Stmt<'a>> {
@@ -342,21 +593,89 @@ impl<'a> Parser<'a> {
         self.assignment()
     }
 
+    fn yield_expression(&mut self) -> ParseResult<Expr<'a>> {
+        if self.function_kind == FunctionKind::Script {
+            self.error(self.previous(), "Can't yield from top-level code.")
+        }
+        let keyword = self.previous().unwrap();
+        let value = if self.check(TokenKind::Semicolon)
+            || self.check(TokenKind::RightParen)
+            || self.check(TokenKind::Comma)
+        {
+            None
+        } else {
+            Some(Box::new(self.assignment()?))
+        };
+
+        Ok(Expr::Yield(expr::Yield { keyword, value }))
+    }
+
     fn assignment(&mut self) -> ParseResult<Expr<'a>> {
-        let expr = self.or()?;
+        if self.match_current(TokenKind::Yield) {
+            return self.yield_expression();
+        }
+
+        let expr = self.conditional()?;
 
-        if self.match_current(TokenKind::Equal) {
-            let equals = self.previous().unwrap();
+        if self.match_current(TokenKind::Equal)
+            || self.match_current(TokenKind::PlusEqual)
+            || self.match_current(TokenKind::MinusEqual)
+            || self.match_current(TokenKind::StarEqual)
+            || self.match_current(TokenKind::SlashEqual)
+            || self.match_current(TokenKind::PercentEqual)
+        {
+            let operator = self.previous().unwrap();
             let value = self.assignment()?;
 
             if let Expr::Variable(expr::Variable { name, .. }) = expr {
                 return Ok(Expr::Assign(expr::Assign {
-                    name: name,
+                    name,
+                    operator,
                     value: Box::from(value),
                 }));
             }
 
-            self.error(Some(equals), "Invalid assignment target.");
+            self.error(Some(operator), "Invalid assignment target.");
+        }
+
+        Ok(expr)
+    }
+
+    /// `a ? b : c ? d : e` groups as `a ? b : (c ? d : e)`. The middle
+    /// operand is delimited by the `:`, so it may be any expression.
+    fn conditional(&mut self) -> ParseResult<Expr<'a>> {
+        let condition = self.coalesce()?;
+
+        if self.match_current(TokenKind::Question) {
+            let question = self.previous().unwrap();
+            let then_branch = self.expression()?;
+            self.consume(
+                TokenKind::Colon,
+                "Expect ':' after then branch of conditional expression.",
+            )?;
+            let else_branch = self.conditional()?;
+            return Ok(Expr::Conditional(expr::Conditional {
+                condition: Box::from(condition),
+                question,
+                then_branch: Box::from(then_branch),
+                else_branch: Box::from(else_branch),
+            }));
+        }
+
+        Ok(condition)
+    }
+
+    fn coalesce(&mut self) -> ParseResult<Expr<'a>> {
+        let mut expr = self.or()?;
+
+        while self.match_current(TokenKind::QuestionQuestion) {
+            let operator = self.previous().unwrap();
+            let right = self.or()?;
+            expr = Expr::Logical(expr::Logical {
+                left: Box::from(expr),
+                operator,
+                right: Box::from(right),
+            })
         }
 
         Ok(expr)
@@ -412,7 +731,7 @@ impl<'a> Parser<'a> {
     }
 
     fn comparison(&mut self) -> ParseResult<Expr<'a>> {
-        let mut expr = self.term()?;
+        let mut expr = self.bit_or()?;
 
         while self.match_current(TokenKind::Greater)
             || self.match_current(TokenKind::GreaterEqual)
@@ -420,7 +739,7 @@ impl<'a> Parser<'a> {
             || self.match_current(TokenKind::LessEqual)
         {
             let operator = self.previous().unwrap();
-            let right = Box::from(self.term()?);
+            let right = Box::from(self.bit_or()?);
             expr = Expr::Binary(expr::Binary {
                 left: Box::from(expr),
                 operator,
@@ -431,6 +750,49 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
+    /// Parses a left-associative chain of `operand`s joined by any of the
+    /// `operators`.
+    fn binary(
+        &mut self,
+        operators: &[TokenKind],
+        operand: fn(&mut Self) -> ParseResult<Expr<'a>>,
+    ) -> ParseResult<Expr<'a>> {
+        let mut expr = operand(self)?;
+
+        while operators.iter().any(|kind| self.match_current(*kind)) {
+            let operator = self.previous().unwrap();
+            let right = operand(self)?;
+            expr = Expr::Binary(expr::Binary {
+                left: Box::from(expr),
+                operator,
+                right: Box::from(right),
+            })
+        }
+
+        Ok(expr)
+    }
+
+    // The bitwise operators sit between comparison and addition, as in
+    // Python, so `a & 1 == 0` means `(a & 1) == 0`.
+    fn bit_or(&mut self) -> ParseResult<Expr<'a>> {
+        self.binary(&[TokenKind::Pipe], Self::bit_xor)
+    }
+
+    fn bit_xor(&mut self) -> ParseResult<Expr<'a>> {
+        self.binary(&[TokenKind::Caret], Self::bit_and)
+    }
+
+    fn bit_and(&mut self) -> ParseResult<Expr<'a>> {
+        self.binary(&[TokenKind::Ampersand], Self::shift)
+    }
+
+    fn shift(&mut self) -> ParseResult<Expr<'a>> {
+        self.binary(
+            &[TokenKind::LessLess, TokenKind::GreaterGreater],
+            Self::term,
+        )
+    }
+
     fn term(&mut self) -> ParseResult<Expr<'a>> {
         let mut expr = self.factor()?;
 
@@ -450,7 +812,11 @@ impl<'a> Parser<'a> {
     fn factor(&mut self) -> ParseResult<Expr<'a>> {
         let mut expr = self.unary()?;
 
-        while self.match_current(TokenKind::Star) || self.match_current(TokenKind::Slash) {
+        while self.match_current(TokenKind::Star)
+            || self.match_current(TokenKind::Slash)
+            || self.match_current(TokenKind::Percent)
+            || self.match_current(TokenKind::TildeSlash)
+        {
             let operator = self.previous().unwrap();
             let right = self.unary()?;
             expr = Expr::Binary(expr::Binary {
@@ -464,7 +830,10 @@ impl<'a> Parser<'a> {
     }
 
     fn unary(&mut self) -> ParseResult<Expr<'a>> {
-        if self.match_current(TokenKind::Bang) || self.match_current(TokenKind::Minus) {
+        if self.match_current(TokenKind::Bang)
+            || self.match_current(TokenKind::Minus)
+            || self.match_current(TokenKind::Tilde)
+        {
             let operator = self.previous().unwrap();
             let right = self.unary()?;
             return Ok(Expr::Unary(expr::Unary {
@@ -473,11 +842,64 @@ impl<'a> Parser<'a> {
             }));
         }
 
-        self.call()
+        if self.match_current(TokenKind::PlusPlus) || self.match_current(TokenKind::MinusMinus) {
+            let operator = self.previous().unwrap();
+            let right = self.unary()?;
+            return match right {
+                Expr::Variable(expr::Variable { name }) => Ok(Expr::Update(expr::Update {
+                    name,
+                    operator,
+                    prefix: true,
+                })),
+                right => {
+                    self.error(Some(operator), "Invalid assignment target.");
+                    Ok(right)
+                }
+            };
+        }
+
+        self.power()
+    }
+
+    /// `**` binds tighter than unary operators on its left, so `-2 ** 2` is
+    /// -4, and is right-associative by parsing its right operand as a unary.
+    fn power(&mut self) -> ParseResult<Expr<'a>> {
+        let expr = self.postfix()?;
+
+        if self.match_current(TokenKind::StarStar) {
+            let operator = self.previous().unwrap();
+            let right = self.unary()?;
+            return Ok(Expr::Binary(expr::Binary {
+                left: Box::from(expr),
+                operator,
+                right: Box::from(right),
+            }));
+        }
+
+        Ok(expr)
+    }
+
+    fn postfix(&mut self) -> ParseResult<Expr<'a>> {
+        let expr = self.call()?;
+
+        if self.match_current(TokenKind::PlusPlus) || self.match_current(TokenKind::MinusMinus) {
+            let operator = self.previous().unwrap();
+            if let Expr::Variable(expr::Variable { name }) = expr {
+                return Ok(Expr::Update(expr::Update {
+                    name,
+                    operator,
+                    prefix: false,
+                }));
+            }
+
+            self.error(Some(operator), "Invalid assignment target.");
+        }
+
+        Ok(expr)
     }
 
     fn finish_call(&mut self, callee: Expr<'a>) -> ParseResult<Expr<'a>> {
-        let mut args: Vec<Expr<'a>> = Vec::new();
+        let mut args: Vec<expr::Argument<'a>> = Vec::new();
 
         if !self.check(TokenKind::RightParen) {
             loop {
@@ -485,7 +907,15 @@ impl<'a> Parser<'a> {
                     self.error(self.peek(), "Can't have more than 255 arguments.")
                 }
 
-                args.push(self.expression()?);
+                let spread = if self.match_current(TokenKind::DotDotDot) {
+                    self.previous()
+                } else {
+                    None
+                };
+                args.push(expr::Argument {
+                    value: self.expression()?,
+                    spread,
+                });
                 if !self.match_current(TokenKind::Comma) {
                     break;
                 }
@@ -515,7 +945,112 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
+    fn interpolation(&mut self) -> ParseResult<Expr<'a>> {
+        let mut parts: Vec<Expr<'a>> = Vec::new();
+
+        loop {
+            let segment = self.previous().unwrap();
+            if !scanner::string_body(segment.lexeme).is_empty() {
+                parts.push(Expr::Literal(expr::Literal { value: segment }));
+            }
+
+            parts.push(self.expression()?);
+
+            if !self.match_current(TokenKind::Interpolation) {
+                break;
+            }
+        }
+
+        let end = self.consume(
+            TokenKind::String,
+            "Expect '}' after interpolated expression.",
+        )?;
+        if !scanner::string_body(end.lexeme).is_empty() {
+            parts.push(Expr::Literal(expr::Literal { value: end }));
+        }
+
+        Ok(Expr::Interpolation(expr::Interpolation { parts, end }))
+    }
+
+    fn lambda(&mut self) -> ParseResult<Expr<'a>> {
+        let keyword = self.previous().unwrap();
+        self.consume(TokenKind::LeftParen, "Expect '(' after 'fun'.")?;
+        let (params, rest) = self.parameters()?;
+        self.consume(TokenKind::LeftBrace, "Expect '{' before function body.")?;
+        let body = self.function_body(FunctionKind::Function)?;
+
+        Ok(Expr::Lambda(expr::Lambda {
+            keyword,
+            params,
+            rest,
+            body,
+            brace: self.previous().unwrap(),
+        }))
+    }
+
+    /// Parameters can hold default values, so rather than checking their
+    /// shape this looks past the matching ')' for a '=>'.
+    fn is_arrow(&self) -> bool {
+        let mut depth = 0;
+
+        for (index, token) in self.tokens.iter().enumerate().skip(self.current) {
+            match token.kind {
+                TokenKind::LeftParen => depth += 1,
+                TokenKind::RightParen => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return self.tokens.get(index + 1).map(|token| token.kind)
+                            == Some(TokenKind::Arrow);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        false
+    }
+
+    fn arrow(&mut self) -> ParseResult<Expr<'a>> {
+        let keyword = self.consume(TokenKind::LeftParen, "Expect '(' before parameters.")?;
+        let (params, rest) = self.parameters()?;
+        let arrow = self.consume(TokenKind::Arrow, "Expect '=>' after parameters.")?;
+
+        let body = if self.match_current(TokenKind::LeftBrace) {
+            self.function_body(FunctionKind::Function)?
+        } else {
+            let enclosing_kind = self.function_kind;
+            self.function_kind = FunctionKind::Function;
+            let value = self.expression();
+            self.function_kind = enclosing_kind;
+
+            vec![Stmt::Return(stmt::Return {
+                keyword: arrow,
+                value: Some(value?),
+            })]
+        };
+
+        Ok(Expr::Lambda(expr::Lambda {
+            keyword,
+            params,
+            rest,
+            body,
+            brace: self.previous().unwrap(),
+        }))
+    }
+
     fn primary(&mut self) -> ParseResult<Expr<'a>> {
+        if self.match_current(TokenKind::Fun) {
+            return self.lambda();
+        }
+
+        if self.match_current(TokenKind::Interpolation) {
+            return self.interpolation();
+        }
+
+        if self.check(TokenKind::LeftParen) && self.is_arrow() {
+            return self.arrow();
+        }
+
         if let Some(token) = self.peek() {
             match token.kind {
                 TokenKind::False
@@ -558,8 +1093,11 @@ impl<'a> Parser<'a> {
                 TokenKind::Class
                 | TokenKind::Fun
                 | TokenKind::Var
+                | TokenKind::Const
+                | TokenKind::Do
                 | TokenKind::For
                 | TokenKind::If
+                | TokenKind::Match
                 | TokenKind::While
                 | TokenKind::Print
                 | TokenKind::Return => return,
@@ -571,7 +1109,7 @@ impl<'a> Parser<'a> {
     }
 }
 
-pub fn parse_tokens<'a>(tokens: &'a Vec<Token<'a>>) -> Option<Vec<Stmt<'a>>> {
+pub fn parse_tokens<'a>(tokens: &'a Vec<Token<'a>>) -> Result<Vec<Stmt<'a>>, Vec<Diagnostic>> {
     let mut parser = Parser::new(tokens);
     let mut statements: Vec<Stmt<'a>> = Default::default();
     while !parser.is_at_end() {
@@ -583,9 +1121,9 @@ pub fn parse_tokens<'a>(tokens: &'a Vec<Token<'a>>) -> Option<Vec<Stmt<'a>>> {
         }
     }
 
-    if parser.had_error {
-        None
+    if parser.errors.is_empty() {
+        Ok(statements)
     } else {
-        Some(statements)
+        Err(parser.errors)
     }
 }
@@ -0,0 +1,162 @@
+use crate::native;
+use crate::value::Value;
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Display;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Capability {
+    FsRead,
+    FsWrite,
+    Env,
+    Process,
+    Net,
+    Time,
+}
+
+impl Capability {
+    pub fn from_name(name: &str) -> Option<Capability> {
+        match name {
+            "fs-read" => Some(Capability::FsRead),
+            "fs-write" => Some(Capability::FsWrite),
+            "env" => Some(Capability::Env),
+            "process" => Some(Capability::Process),
+            "net" => Some(Capability::Net),
+            "time" => Some(Capability::Time),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::FsRead => "fs-read",
+            Capability::FsWrite => "fs-write",
+            Capability::Env => "env",
+            Capability::Process => "process",
+            Capability::Net => "net",
+            Capability::Time => "time",
+        }
+    }
+
+    /// Filesystem capabilities can be narrowed to a set of directories.
+    pub fn is_scoped(&self) -> bool {
+        matches!(self, Capability::FsRead | Capability::FsWrite)
+    }
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "{}", self.name())
+    }
+}
+
+enum Grant {
+    All,
+    Paths(Vec<PathBuf>),
+}
+
+#[derive(Default)]
+pub struct Permissions {
+    grants: HashMap<Capability, Grant>,
+}
+
+impl Permissions {
+    pub fn all() -> Permissions {
+        let mut permissions = Permissions::default();
+        for capability in [
+            Capability::FsRead,
+            Capability::FsWrite,
+            Capability::Env,
+            Capability::Process,
+            Capability::Net,
+            Capability::Time,
+        ] {
+            permissions.grant(capability);
+        }
+        permissions
+    }
+
+    pub fn grant(&mut self, capability: Capability) {
+        self.grants.insert(capability, Grant::All);
+    }
+
+    pub fn grant_path<P: AsRef<Path>>(&mut self, capability: Capability, path: P) {
+        let path = resolve(path.as_ref());
+        match self
+            .grants
+            .entry(capability)
+            .or_insert(Grant::Paths(Vec::new()))
+        {
+            Grant::All => (),
+            Grant::Paths(paths) => paths.push(path),
+        }
+    }
+
+    /// Scoped natives take the path they operate on as their first argument.
+    pub fn check(&self, native: &native::Function, args: &[Value]) -> Result<(), String> {
+        let capability = match native.capability {
+            Some(capability) => capability,
+            None => return Ok(()),
+        };
+
+        match (self.grants.get(&capability), args.first()) {
+            (Some(Grant::All), _) => Ok(()),
+            (Some(Grant::Paths(paths)), Some(Value::String(path))) => {
+                let path = resolve(Path::new(&**path));
+                if paths.iter().any(|allowed| path.starts_with(allowed)) {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "Permission denied: '{}' requires {} access to '{}'.",
+                        native.name,
+                        capability,
+                        path.display()
+                    ))
+                }
+            }
+            // Let the native report the bad argument itself.
+            (Some(Grant::Paths(_)), _) => Ok(()),
+            (None, _) => Err(format!(
+                "Permission denied: '{}' requires {}.",
+                native.name, capability
+            )),
+        }
+    }
+}
+
+/// Resolves symlinks as well as `.` and `..`, so that a scope can't be
+/// escaped with `../` or with a link pointing out of it. A path that doesn't
+/// exist yet, like a file about to be written, is resolved through its
+/// nearest existing parent.
+fn resolve(path: &Path) -> PathBuf {
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir().unwrap_or_default().join(path)
+    };
+
+    for ancestor in absolute.ancestors() {
+        if let Ok(canonical) = ancestor.canonicalize() {
+            let missing = absolute.strip_prefix(ancestor).unwrap_or(Path::new(""));
+            return normalize(&canonical.join(missing));
+        }
+    }
+    normalize(&absolute)
+}
+
+/// Resolves `.` and `..` lexically, for the part of a path that doesn't
+/// exist and so can't contain links.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
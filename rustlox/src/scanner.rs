@@ -15,6 +15,11 @@ pub enum TokenKind {
     Semicolon,
     Slash,
     Star,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    Colon,
     // One or two character tokens.
     Bang,
     BangEqual,
@@ -24,20 +29,47 @@ pub enum TokenKind {
     GreaterEqual,
     Less,
     LessEqual,
+    LessLess,
+    GreaterGreater,
+    Tilde,
+    DotDot,
+    DotDotDot,
+    DotDotEqual,
+    Question,
+    QuestionQuestion,
+    Arrow,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
+    PlusPlus,
+    MinusMinus,
+    StarStar,
+    /// Integer division is spelled `~/`, as in Dart, because `//` already
+    /// starts a comment.
+    TildeSlash,
     // Literals.
     Identifier,
     String,
+    Interpolation,
     Number,
     // Keywords.
     And,
     Break,
+    Case,
     Class,
+    Const,
     Continue,
+    Default,
+    Do,
     Else,
     False,
     For,
     Fun,
     If,
+    In,
+    Match,
     Nil,
     Or,
     Print,
@@ -47,6 +79,7 @@ pub enum TokenKind {
     True,
     Var,
     While,
+    Yield,
 
     Error,
 }
@@ -59,19 +92,23 @@ pub struct Token<'a> {
 }
 
 struct Scanner<'a> {
-    source: &'a String,
+    source: &'a str,
     pub lines: i32,
     start: usize,
     iter: Peekable<std::str::CharIndices<'a>>,
+    /// Unclosed braces inside each `${` we're currently scanning, and the
+    /// line it was opened on.
+    interpolations: Vec<(usize, i32)>,
 }
 
 impl<'a> Scanner<'a> {
-    fn new(source: &'a String) -> Scanner<'a> {
+    fn new(source: &'a str) -> Scanner<'a> {
         Scanner {
             source,
             lines: 1,
             start: 0,
             iter: source.char_indices().peekable(),
+            interpolations: Vec::new(),
         }
     }
 
@@ -87,7 +124,8 @@ impl<'a> Scanner<'a> {
         self.iter.next_if(|&(_, c)| c == expected).is_some()
     }
 
-    fn skip_whitespace(&mut self) {
+    /// Returns an error token if a block comment is never closed.
+    fn skip_whitespace(&mut self) -> Option<Token<'a>> {
         while let Some((_, c)) = self.iter.peek() {
             match *c {
                 ' ' | '\r' | '\t' => {
@@ -97,16 +135,45 @@ impl<'a> Scanner<'a> {
                     self.lines += 1;
                     self.advance();
                 }
-                '/' => {
-                    if let Some((_, '/')) = self.peek_next() {
-                        self.consume_while(|c| c != '\n');
-                    } else {
-                        return;
+                '/' => match self.peek_next() {
+                    Some((_, '/')) => self.consume_while(|c| c != '\n'),
+                    Some((_, '*')) => {
+                        if let Some(error) = self.block_comment() {
+                            return Some(error);
+                        }
                     }
+                    _ => return None,
+                },
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// Block comments nest, so `/* a /* b */ c */` is a single comment.
+    fn block_comment(&mut self) -> Option<Token<'a>> {
+        let line = self.lines;
+        self.advance();
+        self.advance();
+
+        let mut depth = 1;
+        while depth > 0 {
+            match self.advance() {
+                Some((_, '/')) if self.match_current('*') => depth += 1,
+                Some((_, '*')) if self.match_current('/') => depth -= 1,
+                Some((_, '\n')) => self.lines += 1,
+                Some(_) => {}
+                // Point at the opening, since the end of the file says little.
+                None => {
+                    return Some(Token {
+                        kind: TokenKind::Error,
+                        line,
+                        lexeme: "Unterminated block comment.",
+                    })
                 }
-                _ => return,
             }
         }
+        None
     }
 
     fn get_lexeme(&mut self) -> &'a str {
@@ -121,7 +188,7 @@ impl<'a> Scanner<'a> {
 
     fn make_token(&mut self, kind: TokenKind) -> Token<'a> {
         Token {
-            kind: kind,
+            kind,
             line: self.lines,
             lexeme: self.get_lexeme(),
         }
@@ -136,29 +203,103 @@ impl<'a> Scanner<'a> {
     }
 
     fn peek_next(&mut self) -> Option<(usize, char)> {
-        let (n, _) = self.iter.peek()?;
-
-        let byte = self.source.as_bytes().get(n + 1)?;
-
-        return Some((n + 1, *byte as char));
+        let mut iter = self.iter.clone();
+        iter.next();
+        iter.next()
     }
 
     fn string(&mut self) -> Token<'a> {
         while let Some((_, c)) = self.iter.next_if(|&(_, c)| c != '"') {
-            if c == '\n' {
-                self.lines += 1;
+            match c {
+                '\n' => self.lines += 1,
+                // Skip the escaped character so that `\"` doesn't end the string.
+                '\\' => {
+                    if let Some((_, '\n')) = self.advance() {
+                        self.lines += 1;
+                    }
+                }
+                '$' if self.match_current('{') => {
+                    self.interpolations.push((0, self.lines));
+                    return self.string_token(TokenKind::Interpolation);
+                }
+                _ => (),
             }
         }
 
-        if self.match_current('"') {
-            self.make_token(TokenKind::String)
-        } else {
-            self.make_error_token("Unterminated string.")
+        if !self.match_current('"') {
+            return self
+                .unclosed_interpolation()
+                .unwrap_or_else(|| self.make_error_token("Unterminated string."));
+        }
+
+        self.string_token(TokenKind::String)
+    }
+
+    /// Reports a `${` left open at the end of the file on the line it was
+    /// opened on. A string inside it will have run to the end of the file
+    /// too, but it's the missing `}` that's at fault.
+    fn unclosed_interpolation(&mut self) -> Option<Token<'a>> {
+        let (_, line) = self.interpolations.pop()?;
+        self.interpolations.clear();
+        Some(Token {
+            kind: TokenKind::Error,
+            line,
+            lexeme: "Expect '}' after interpolation.",
+        })
+    }
+
+    fn string_token(&mut self, kind: TokenKind) -> Token<'a> {
+        match unescape(string_body(self.get_lexeme())) {
+            Ok(_) => self.make_token(kind),
+            Err(message) => self.make_error_token(message),
+        }
+    }
+
+    fn raw_string(&mut self) -> Token<'a> {
+        let mut hashes = 0;
+        while self.match_current('#') {
+            hashes += 1;
+        }
+
+        if !self.match_current('"') {
+            return self.make_error_token("Expect '\"' to start raw string.");
         }
+
+        while let Some((_, c)) = self.advance() {
+            match c {
+                '\n' => self.lines += 1,
+                '"' => {
+                    let mut closing = 0;
+                    while closing < hashes && self.match_current('#') {
+                        closing += 1;
+                    }
+                    if closing == hashes {
+                        return self.make_token(TokenKind::String);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        self.make_error_token("Unterminated string.")
     }
 
+    /// Takes the whole run of digits, letters and separators, so the compiler
+    /// can report a malformed literal as one token rather than it being split
+    /// into a number and an identifier.
     fn number(&mut self) -> Token<'a> {
-        self.consume_while(|c| c.is_digit(10));
+        fn literal_char(c: char) -> bool {
+            c.is_ascii_alphanumeric() || c == '_'
+        }
+
+        self.consume_while(literal_char);
+        let prefixed = matches!(
+            self.get_lexeme().get(..2),
+            Some("0x" | "0X" | "0o" | "0O" | "0b" | "0B")
+        );
+        if prefixed {
+            return self.make_token(TokenKind::Number);
+        }
 
         // Look for a fractional part.
         if matches!(self.iter.peek(), Some((_, '.')))
@@ -166,26 +307,41 @@ impl<'a> Scanner<'a> {
         {
             // Consume the ".".
             self.advance();
-            self.consume_while(|c| c.is_digit(10));
+            self.consume_while(literal_char);
+        }
+
+        // And for a signed exponent, since the sign isn't part of the run.
+        if self.get_lexeme().ends_with(['e', 'E'])
+            && matches!(self.iter.peek(), Some((_, '+' | '-')))
+            && matches!(self.peek_next(), Some((_, '0'..='9')))
+        {
+            self.advance();
+            self.consume_while(literal_char);
         }
 
         self.make_token(TokenKind::Number)
     }
 
     fn identifier(&mut self) -> Token<'a> {
-        self.consume_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        self.consume_while(|c| c.is_alphanumeric() || c == '_');
 
         let lexeme = self.get_lexeme();
         let kind = match lexeme {
             "and" => TokenKind::And,
             "break" => TokenKind::Break,
+            "case" => TokenKind::Case,
             "class" => TokenKind::Class,
+            "const" => TokenKind::Const,
             "continue" => TokenKind::Continue,
+            "default" => TokenKind::Default,
+            "do" => TokenKind::Do,
             "else" => TokenKind::Else,
             "false" => TokenKind::False,
             "for" => TokenKind::For,
             "fun" => TokenKind::Fun,
             "if" => TokenKind::If,
+            "in" => TokenKind::In,
+            "match" => TokenKind::Match,
             "nil" => TokenKind::Nil,
             "or" => TokenKind::Or,
             "print" => TokenKind::Print,
@@ -195,12 +351,13 @@ impl<'a> Scanner<'a> {
             "true" => TokenKind::True,
             "var" => TokenKind::Var,
             "while" => TokenKind::While,
+            "yield" => TokenKind::Yield,
             _ => TokenKind::Identifier,
         };
 
         Token {
-            kind: kind,
-            lexeme: lexeme,
+            kind,
+            lexeme,
             line: self.lines,
         }
     }
@@ -210,23 +367,109 @@ impl<'a> Iterator for Scanner<'a> {
     type Item = Token<'a>;
 
     fn next(&mut self) -> Option<Token<'a>> {
-        self.skip_whitespace();
+        if let Some(error) = self.skip_whitespace() {
+            return Some(error);
+        }
 
-        let (start, c) = self.advance()?;
+        let Some((start, c)) = self.advance() else {
+            return self.unclosed_interpolation();
+        };
         self.start = start;
 
         let token = match c {
             '(' => self.make_token(TokenKind::LeftParen),
             ')' => self.make_token(TokenKind::RightParen),
-            '{' => self.make_token(TokenKind::LeftBrace),
-            '}' => self.make_token(TokenKind::RightBrace),
+            '{' => {
+                if let Some((depth, _)) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                self.make_token(TokenKind::LeftBrace)
+            }
+            '}' => match self.interpolations.last_mut() {
+                Some((0, _)) => {
+                    self.interpolations.pop();
+                    self.string()
+                }
+                Some((depth, _)) => {
+                    *depth -= 1;
+                    self.make_token(TokenKind::RightBrace)
+                }
+                None => self.make_token(TokenKind::RightBrace),
+            },
             ';' => self.make_token(TokenKind::Semicolon),
             ',' => self.make_token(TokenKind::Comma),
-            '.' => self.make_token(TokenKind::Dot),
-            '-' => self.make_token(TokenKind::Minus),
-            '+' => self.make_token(TokenKind::Plus),
-            '*' => self.make_token(TokenKind::Star),
-            '/' => self.make_token(TokenKind::Slash),
+            ':' => self.make_token(TokenKind::Colon),
+            '?' => {
+                if self.match_current('?') {
+                    self.make_token(TokenKind::QuestionQuestion)
+                } else {
+                    self.make_token(TokenKind::Question)
+                }
+            }
+            '.' => {
+                if self.match_current('.') {
+                    if self.match_current('=') {
+                        self.make_token(TokenKind::DotDotEqual)
+                    } else if self.match_current('.') {
+                        self.make_token(TokenKind::DotDotDot)
+                    } else {
+                        self.make_token(TokenKind::DotDot)
+                    }
+                } else {
+                    self.make_token(TokenKind::Dot)
+                }
+            }
+            '-' => {
+                if self.match_current('=') {
+                    self.make_token(TokenKind::MinusEqual)
+                } else if self.match_current('-') {
+                    self.make_token(TokenKind::MinusMinus)
+                } else {
+                    self.make_token(TokenKind::Minus)
+                }
+            }
+            '+' => {
+                if self.match_current('=') {
+                    self.make_token(TokenKind::PlusEqual)
+                } else if self.match_current('+') {
+                    self.make_token(TokenKind::PlusPlus)
+                } else {
+                    self.make_token(TokenKind::Plus)
+                }
+            }
+            '*' => {
+                if self.match_current('*') {
+                    self.make_token(TokenKind::StarStar)
+                } else if self.match_current('=') {
+                    self.make_token(TokenKind::StarEqual)
+                } else {
+                    self.make_token(TokenKind::Star)
+                }
+            }
+            '/' => {
+                if self.match_current('=') {
+                    self.make_token(TokenKind::SlashEqual)
+                } else {
+                    self.make_token(TokenKind::Slash)
+                }
+            }
+            '%' => {
+                if self.match_current('=') {
+                    self.make_token(TokenKind::PercentEqual)
+                } else {
+                    self.make_token(TokenKind::Percent)
+                }
+            }
+            '&' => self.make_token(TokenKind::Ampersand),
+            '|' => self.make_token(TokenKind::Pipe),
+            '^' => self.make_token(TokenKind::Caret),
+            '~' => {
+                if self.match_current('/') {
+                    self.make_token(TokenKind::TildeSlash)
+                } else {
+                    self.make_token(TokenKind::Tilde)
+                }
+            }
             '!' => {
                 if self.match_current('=') {
                     self.make_token(TokenKind::BangEqual)
@@ -237,6 +480,8 @@ impl<'a> Iterator for Scanner<'a> {
             '=' => {
                 if self.match_current('=') {
                     self.make_token(TokenKind::EqualEqual)
+                } else if self.match_current('>') {
+                    self.make_token(TokenKind::Arrow)
                 } else {
                     self.make_token(TokenKind::Equal)
                 }
@@ -244,6 +489,8 @@ impl<'a> Iterator for Scanner<'a> {
             '<' => {
                 if self.match_current('=') {
                     self.make_token(TokenKind::LessEqual)
+                } else if self.match_current('<') {
+                    self.make_token(TokenKind::LessLess)
                 } else {
                     self.make_token(TokenKind::Less)
                 }
@@ -251,13 +498,16 @@ impl<'a> Iterator for Scanner<'a> {
             '>' => {
                 if self.match_current('=') {
                     self.make_token(TokenKind::GreaterEqual)
+                } else if self.match_current('>') {
+                    self.make_token(TokenKind::GreaterGreater)
                 } else {
                     self.make_token(TokenKind::Greater)
                 }
             }
             '"' => self.string(),
+            'r' if matches!(self.iter.peek(), Some((_, '"' | '#'))) => self.raw_string(),
             '0'..='9' => self.number(),
-            'a'..='z' | 'A'..='Z' | '_' => self.identifier(),
+            c if c.is_alphabetic() || c == '_' => self.identifier(),
             _ => self.make_error_token("Unexpected character."),
         };
 
@@ -265,11 +515,71 @@ impl<'a> Iterator for Scanner<'a> {
     }
 }
 
-pub fn scan_tokens<'a>(source: &'a String) -> Vec<Token<'a>> {
-    let mut scanner = Scanner::new(source);
-    let mut tokens: Vec<Token<'a>> = Vec::new();
-    while let Some(token) = scanner.next() {
-        tokens.push(token)
+/// Decodes the escape sequences in the body of a string literal.
+pub fn unescape(body: &str) -> Result<String, &'static str> {
+    let mut result = String::with_capacity(body.len());
+    let mut chars = body.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        let escaped = match chars.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some('"') => '"',
+            Some('$') => '$',
+            Some('u') => {
+                if chars.next() != Some('{') {
+                    return Err("Expect '{' after '\\u'.");
+                }
+                let mut code = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) if c.is_ascii_hexdigit() && code.len() < 6 => code.push(c),
+                        _ => return Err("Invalid unicode escape sequence."),
+                    }
+                }
+                u32::from_str_radix(&code, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or("Invalid unicode escape sequence.")?
+            }
+            _ => return Err("Invalid escape sequence."),
+        };
+        result.push(escaped);
+    }
+
+    Ok(result)
+}
+
+/// Strips the delimiters from a string literal or one segment of an
+/// interpolated string: `"...`, `}...` and `...${`, `..."`.
+pub fn string_body(lexeme: &str) -> &str {
+    let body = &lexeme[1..];
+    body.strip_suffix("${")
+        .or_else(|| body.strip_suffix('"'))
+        .unwrap_or(body)
+}
+
+/// Strips the quotes from a string literal and decodes it. Raw strings
+/// (`r"..."`, `r#"..."#`) are taken verbatim.
+pub fn string_value(lexeme: &str) -> Result<String, &'static str> {
+    match lexeme.strip_prefix('r') {
+        Some(raw) => {
+            let hashes = raw.len() - raw.trim_start_matches('#').len();
+            Ok(raw[hashes + 1..raw.len() - hashes - 1].to_string())
+        }
+        None => unescape(string_body(lexeme)),
     }
-    tokens
+}
+
+pub fn scan_tokens<'a>(source: &'a str) -> Vec<Token<'a>> {
+    Scanner::new(source).collect()
 }
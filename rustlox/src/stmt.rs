@@ -10,11 +10,22 @@ pub struct Block<'a> {
 #[derive(Debug)]
 pub struct Break<'a> {
     pub keyword: &'a Token<'a>,
+    pub label: Option<&'a Token<'a>>,
 }
 
 #[derive(Debug)]
 pub struct Continue<'a> {
     pub keyword: &'a Token<'a>,
+    pub label: Option<&'a Token<'a>>,
+}
+
+/// `do body while (condition);`
+#[derive(Debug)]
+pub struct DoWhile<'a> {
+    pub label: Option<&'a Token<'a>>,
+    pub body: Box<Stmt<'a>>,
+    pub condition: Expr<'a>,
+    pub end: &'a Token<'a>,
 }
 
 #[derive(Debug)]
@@ -24,10 +35,24 @@ pub struct Expression<'a> {
 
 #[derive(Debug)]
 pub struct For<'a> {
+    pub label: Option<&'a Token<'a>>,
     pub initializer: Option<Box<Stmt<'a>>>,
     pub condition: Option<Expr<'a>>,
     pub increment: Option<Expr<'a>>,
+    pub paren: &'a Token<'a>,
+    pub body: Box<Stmt<'a>>,
+    pub end: &'a Token<'a>,
+}
+
+/// `for (name in iterable) body`.
+#[derive(Debug)]
+pub struct ForIn<'a> {
+    pub label: Option<&'a Token<'a>>,
+    pub name: &'a Token<'a>,
+    pub keyword: &'a Token<'a>,
+    pub iterable: Expr<'a>,
     pub body: Box<Stmt<'a>>,
+    pub end: &'a Token<'a>,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -39,17 +64,57 @@ pub enum FunctionKind {
 #[derive(Debug)]
 pub struct Function<'a> {
     pub name: &'a Token<'a>,
-    pub params: Vec<&'a Token<'a>>,
+    pub params: Vec<Param<'a>>,
+    pub rest: Option<&'a Token<'a>>,
     pub body: Vec<Stmt<'a>>,
-    pub kind: FunctionKind,
     pub brace: &'a Token<'a>,
 }
 
+#[derive(Debug)]
+pub struct Param<'a> {
+    pub name: &'a Token<'a>,
+    /// Used when the argument is left out or passed as nil.
+    pub default: Option<Expr<'a>>,
+}
+
 #[derive(Debug)]
 pub struct If<'a> {
     pub condition: Expr<'a>,
     pub then_branch: Box<Stmt<'a>>,
+    pub then_end: &'a Token<'a>,
     pub else_branch: Option<Box<Stmt<'a>>>,
+    pub end: &'a Token<'a>,
+}
+
+#[derive(Debug)]
+pub enum Pattern<'a> {
+    /// Matches anything equal to the value, compared with `==`.
+    Value(Expr<'a>),
+    /// `start..end` excludes `end`, `start..=end` includes it.
+    Range {
+        start: Expr<'a>,
+        end: Expr<'a>,
+        inclusive: bool,
+    },
+    /// `_`, which only makes sense together with a guard.
+    Wildcard,
+}
+
+#[derive(Debug)]
+pub struct Case<'a> {
+    pub keyword: &'a Token<'a>,
+    pub patterns: Vec<Pattern<'a>>,
+    pub guard: Option<Expr<'a>>,
+    pub body: Vec<Stmt<'a>>,
+}
+
+#[derive(Debug)]
+pub struct Match<'a> {
+    pub keyword: &'a Token<'a>,
+    pub subject: Expr<'a>,
+    pub cases: Vec<Case<'a>>,
+    pub default: Option<Vec<Stmt<'a>>>,
+    pub brace: &'a Token<'a>,
 }
 
 #[derive(Debug)]
@@ -68,12 +133,16 @@ pub struct Return<'a> {
 pub struct Var<'a> {
     pub name: &'a Token<'a>,
     pub initializer: Option<Expr<'a>>,
+    /// Declared with `const`, so it can't be assigned to.
+    pub constant: bool,
 }
 
 #[derive(Debug)]
 pub struct While<'a> {
+    pub label: Option<&'a Token<'a>>,
     pub condition: Expr<'a>,
     pub body: Box<Stmt<'a>>,
+    pub end: &'a Token<'a>,
 }
 
 #[derive(Debug)]
@@ -81,10 +150,13 @@ pub enum Stmt<'a> {
     Block(Block<'a>),
     Break(Break<'a>),
     Continue(Continue<'a>),
+    DoWhile(DoWhile<'a>),
     Expression(Expression<'a>),
     For(For<'a>),
+    ForIn(ForIn<'a>),
     Function(Function<'a>),
     If(If<'a>),
+    Match(Match<'a>),
     Print(Print<'a>),
     Return(Return<'a>),
     Var(Var<'a>),
@@ -1,7 +1,6 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt::Display;
-use std::ops;
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub struct Handle(usize);
@@ -15,19 +14,12 @@ impl Handle {
         })
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(string: &str) -> Handle {
         with_interner(|interner| interner.intern(string))
     }
 }
 
-impl ops::Add<Handle> for Handle {
-    type Output = Handle;
-    fn add(self, other: Handle) -> <Self as std::ops::Add<Handle>>::Output {
-        let string = format!("{}{}", self, other);
-        Handle::from_str(string.as_str())
-    }
-}
-
 impl Display for Handle {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
         write!(f, "{}", self.as_str().string)
@@ -66,7 +58,7 @@ fn with_interner<T, F: FnOnce(&mut Interner) -> T>(f: F) -> T {
     thread_local!(static INTERNER: RefCell<Interner> = {
         RefCell::new(Interner::new())
     });
-    INTERNER.with(|interner| f(&mut *interner.borrow_mut()))
+    INTERNER.with(|interner| f(&mut interner.borrow_mut()))
 }
 
 pub struct InternedString {
@@ -1,29 +1,55 @@
+use crate::bigint::BigInt;
 use crate::chunk::Chunk;
 use crate::native;
+use crate::number;
 use crate::string;
+use crate::vm::Fiber;
+use std::borrow::Cow;
 use std::cell::RefCell;
+use std::fmt::Display;
 use std::rc::Rc;
 
 #[derive(Clone, Debug)]
 pub struct Function {
+    /// The number of required parameters.
     pub arity: usize,
+    /// Parameters with a default value, which follow the required ones.
+    pub optional: usize,
+    /// Whether a rest parameter collects any further arguments into a list.
+    pub variadic: bool,
     pub chunk: Rc<Chunk>,
     pub name: string::Handle,
+    /// Lambdas have no name, so they're described by the line they were
+    /// defined on. That name is built when asked for rather than interned,
+    /// since interned strings are never freed.
+    pub lambda_line: Option<i32>,
     pub upvalue_count: usize,
 }
 
 impl Function {
-    pub fn get_name(&self) -> &'static str {
-        match self.name.as_str().string {
-            "" => "<script>",
-            value => value,
+    pub fn get_name(&self) -> Cow<'static, str> {
+        match (self.lambda_line, self.name.as_str().string) {
+            (Some(line), _) => Cow::Owned(format!("<lambda@line {}>", line)),
+            (None, "") => Cow::Borrowed("<script>"),
+            (None, value) => Cow::Borrowed(value),
         }
     }
 
-    pub fn print(&self) {
+    /// Describes the accepted argument counts for arity errors.
+    pub fn expected_arity(&self) -> String {
+        match (self.optional, self.variadic) {
+            (0, false) => self.arity.to_string(),
+            (optional, false) => format!("{} to {}", self.arity, self.arity + optional),
+            (_, true) => format!("at least {}", self.arity),
+        }
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
         match self.get_name() {
-            "<script>" => print!("<script>"),
-            name => print!("<fn {}>", name),
+            name if name.starts_with('<') => write!(f, "{}", name),
+            name => write!(f, "<fn {}>", name),
         }
     }
 }
@@ -32,13 +58,11 @@ impl Function {
 pub struct Closure {
     pub function: Function,
     pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
-    pub upvalue_count: usize,
 }
 
 impl Closure {
     pub fn new(function: Function) -> Closure {
         Closure {
-            upvalue_count: function.upvalue_count,
             upvalues: Vec::with_capacity(function.upvalue_count),
             function,
         }
@@ -90,21 +114,20 @@ impl Upvalue {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub enum Value {
     Bool(bool),
     Number(f64),
+    Int(i64),
+    BigInt(Rc<BigInt>),
+    #[default]
     Nil,
-    String(string::Handle),
+    String(Rc<str>),
     Function(Function),
     Native(native::Function),
     Closure(Closure),
-}
-
-impl Default for Value {
-    fn default() -> Self {
-        Value::Nil
-    }
+    List(Rc<RefCell<Vec<Value>>>),
+    Fiber(Rc<RefCell<Fiber>>),
 }
 
 impl std::fmt::Debug for Value {
@@ -112,11 +135,15 @@ impl std::fmt::Debug for Value {
         match self {
             Value::Bool(value) => write!(f, "Value::Bool({})", value),
             Value::Number(value) => write!(f, "Value::Number({})", value),
+            Value::Int(value) => write!(f, "Value::Int({})", value),
+            Value::BigInt(value) => write!(f, "Value::BigInt({})", value),
             Value::Nil => write!(f, "Value::Nil"),
             Value::String(value) => write!(f, "Value::String({})", value),
             Value::Function(value) => write!(f, "Value::Function({:?})", value),
             Value::Native(_) => write!(f, "Value::Native(<native fn>)"),
             Value::Closure(value) => write!(f, "Value::Closure({:?})", value),
+            Value::List(value) => write!(f, "Value::List({:?})", value.borrow()),
+            Value::Fiber(_) => write!(f, "Value::Fiber(<fiber>)"),
         }
     }
 }
@@ -127,9 +154,19 @@ impl PartialEq for Value {
             (Value::Bool(a), Value::Bool(b)) => a == b,
             (Value::Nil, Value::Nil) => true,
             (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Int(a), Value::Number(b)) | (Value::Number(b), Value::Int(a)) => {
+                number::int_equals_float(*a, *b)
+            }
+            (Value::BigInt(a), b) | (b, Value::BigInt(a)) => match b {
+                Value::BigInt(b) => a == b,
+                b => number::big_equals(a, b),
+            },
             (Value::String(a), Value::String(b)) => a == b,
             (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(&a.chunk, &b.chunk),
-            (Value::Native(a), Value::Native(b)) => *a as usize == *b as usize,
+            (Value::Native(a), Value::Native(b)) => a.function as usize == b.function as usize,
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+            (Value::Fiber(a), Value::Fiber(b)) => Rc::ptr_eq(a, b),
             _ => false,
         }
     }
@@ -137,26 +174,37 @@ impl PartialEq for Value {
 
 impl Value {
     pub fn is_falsy(&self) -> bool {
-        match self {
-            Value::Nil | Value::Bool(false) => true,
-            _ => false,
-        }
+        matches!(self, Value::Nil | Value::Bool(false))
     }
+}
 
-    pub fn print(&self) {
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
         match self {
-            Value::Bool(value) => print!("{}", value),
-            Value::Number(value) => print!("{}", value),
-            Value::String(value) => print!("{}", value),
-            Value::Function(function) => function.print(),
-            Value::Native(_) => print!("<native fn>"),
-            Value::Closure(closure) => closure.function.print(),
-            Value::Nil => print!("nil"),
+            Value::Bool(value) => write!(f, "{}", value),
+            // Whole floats keep their `.0` so they can be told apart from ints.
+            Value::Number(value) if value.is_finite() && value.fract() == 0.0 => {
+                write!(f, "{}.0", value)
+            }
+            Value::Number(value) => write!(f, "{}", value),
+            Value::Int(value) => write!(f, "{}", value),
+            Value::BigInt(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Function(function) => write!(f, "{}", function),
+            Value::Native(_) => write!(f, "<native fn>"),
+            Value::Closure(closure) => write!(f, "{}", closure.function),
+            Value::List(list) => {
+                write!(f, "[")?;
+                for (i, value) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Value::Fiber(_) => write!(f, "<fiber>"),
+            Value::Nil => write!(f, "nil"),
         }
     }
-
-    pub fn println(&self) {
-        self.print();
-        println!("");
-    }
 }
@@ -1,26 +1,29 @@
+use crate::bigint::BigInt;
 use crate::chunk::*;
 use crate::compiler::*;
-use crate::native;
+use crate::error::{Frame, InterpretError, RuntimeError, RuntimeErrorKind};
+use crate::limits::{Limit, Limits};
+use crate::native::{self, NativeResult};
+use crate::number;
+use crate::permissions::{Capability, Permissions};
 use crate::scanner;
-use crate::string;
 use crate::value::*;
 use std::cell::RefCell;
-use std::collections::HashMap;
-use std::convert::TryInto;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::convert::{TryFrom, TryInto};
+use std::io::{self, BufRead, Write};
+use std::mem;
 use std::rc::Rc;
-
-fn with_vm<T, F: FnOnce(&mut VM) -> T>(f: F) -> T {
-    thread_local!(static STATIC_VM: RefCell<VM> = {
-        RefCell::new(VM::new())
-    });
-    STATIC_VM.with(|vm| f(&mut *vm.borrow_mut()))
-}
+use std::time::Instant;
 
 #[derive(Default)]
 struct CallFrame {
     closure: Option<Closure>,
     ip: usize,
     starts_at: usize,
+    /// How many calls have replaced this frame's function by tail calling.
+    tail_calls: usize,
 }
 
 impl CallFrame {
@@ -28,72 +31,255 @@ impl CallFrame {
         self.closure = None;
         self.starts_at = 0;
         self.ip = 0;
+        self.tail_calls = 0;
     }
 }
 
-#[derive(PartialEq)]
-pub enum InterpretError {
-    CompileError,
-    RuntimeError,
-    InternalError(&'static str),
-}
-
 const CALL_FRAME_MAX: usize = 64;
 const CALL_FRAME_DEFAULT: CallFrame = CallFrame {
     closure: None,
     ip: 0,
     starts_at: 0,
+    tail_calls: 0,
 };
 const STACK_MAX: usize = 256;
 const STACK_DEFAULT: Value = Value::Nil;
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+#[derive(Copy, Clone, PartialEq)]
+enum FiberStatus {
+    Suspended,
+    Running,
+    /// Returned from its function or failed with an error.
+    Done,
+}
+
+/// A coroutine with its own value stack, frames and open upvalues. While it
+/// runs they are swapped into the `VM` and the fiber holds those of whoever
+/// resumed it instead. The stacks are boxed, so upvalues keep pointing at
+/// the right slots through every swap.
+pub struct Fiber {
+    stack: Box<[Value; STACK_MAX]>,
+    stack_count: usize,
+    frames: Box<[CallFrame; CALL_FRAME_MAX]>,
+    frame_count: usize,
+    open_upvalues: Option<Rc<RefCell<Upvalue>>>,
+    status: FiberStatus,
+}
+
+impl Fiber {
+    /// The heap taken up by a fiber's stack and frames.
+    pub const SIZE: usize =
+        mem::size_of::<[Value; STACK_MAX]>() + mem::size_of::<[CallFrame; CALL_FRAME_MAX]>();
+
+    /// The value of the first `resume` becomes the function's argument when
+    /// it takes one and is dropped into the callee's slot otherwise.
+    pub fn new(closure: Closure) -> std::result::Result<Fiber, String> {
+        let function = &closure.function;
+        let params = function.arity + function.optional;
+        if params > 1 || function.variadic {
+            return Err("Fiber function can take at most one parameter.".to_string());
+        }
+
+        let mut fiber = Fiber {
+            stack: Box::new([STACK_DEFAULT; STACK_MAX]),
+            stack_count: 1 + params,
+            frames: Box::new([CALL_FRAME_DEFAULT; CALL_FRAME_MAX]),
+            frame_count: 1,
+            open_upvalues: None,
+            status: FiberStatus::Suspended,
+        };
+        fiber.stack[0] = Value::Closure(closure.clone());
+        fiber.frames[0].closure = Some(closure);
+        Ok(fiber)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.status == FiberStatus::Done
+    }
+}
+
+impl Drop for Fiber {
+    /// Closures can outlive a suspended fiber, so the upvalues still open on
+    /// its stack are closed before the stack goes away.
+    fn drop(&mut self) {
+        while let Some(upvalue) = self.open_upvalues.take() {
+            let mut upvalue = upvalue.borrow_mut();
+            self.open_upvalues = upvalue.next.take();
+            upvalue.close();
+        }
+    }
+}
 
 pub struct VM {
-    globals: HashMap<&'static str, Value>,
+    globals: HashMap<Rc<str>, Value>,
+    /// Globals declared with `const`. Redeclaring the name replaces the
+    /// constant, which keeps the REPL usable.
+    constants: HashSet<Rc<str>>,
 
-    stack: [Value; STACK_MAX],
+    stack: Box<[Value; STACK_MAX]>,
     stack_count: usize,
 
-    frames: [CallFrame; CALL_FRAME_MAX],
+    frames: Box<[CallFrame; CALL_FRAME_MAX]>,
     frame_count: usize,
 
     open_upvalues: Option<Rc<RefCell<Upvalue>>>,
+    /// Fibers that are running, innermost last. Each one holds the state of
+    /// the one before it, or of the script for the first.
+    fibers: Vec<Rc<RefCell<Fiber>>>,
+
+    limits: Limits,
+    permissions: Permissions,
+    instruction_count: u64,
+    deadline: Option<Instant>,
+    heap_size: usize,
+
+    stdout: Box<dyn Write>,
+    stderr: Box<dyn Write>,
+    /// The only reader of the input, shared by `readLine` and the REPL so
+    /// neither loses what the other has buffered.
+    stdin: Box<dyn BufRead>,
+
+    rng: native::math::Rng,
 }
 
 type Result<T> = std::result::Result<T, InterpretError>;
 
-pub fn interpret(source: &String) -> Result<()> {
-    with_vm(|vm| {
-        let tokens = scanner::scan_tokens(source);
-        if tokens.is_empty() {
-            return Ok(());
-        }
-        let closure = Closure::new(compile(tokens)?);
-        vm.push(Value::Closure(closure.clone()))?;
-        vm.call(closure, 0).ok();
-        vm.run()
-    })
+impl Default for VM {
+    fn default() -> Self {
+        VM::new()
+    }
 }
 
 impl VM {
     pub fn new() -> VM {
         let mut vm: VM = VM {
             globals: Default::default(),
+            constants: Default::default(),
 
             stack_count: Default::default(),
-            stack: [STACK_DEFAULT; STACK_MAX],
+            stack: Box::new([STACK_DEFAULT; STACK_MAX]),
 
             frame_count: Default::default(),
-            frames: [CALL_FRAME_DEFAULT; CALL_FRAME_MAX],
+            frames: Box::new([CALL_FRAME_DEFAULT; CALL_FRAME_MAX]),
 
             open_upvalues: Default::default(),
+            fibers: Vec::new(),
+
+            limits: Default::default(),
+            permissions: Default::default(),
+            instruction_count: 0,
+            deadline: None,
+            heap_size: 0,
+
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+            stdin: Box::new(io::BufReader::new(io::stdin())),
+
+            rng: native::math::Rng::from_time(),
         };
 
-        vm.define_native("clock", native::clock);
+        vm.define_native("clock", 0, None, native::clock);
+        vm.define_native("readFile", 1, Some(Capability::FsRead), native::read_file);
+        vm.define_native(
+            "writeFile",
+            2,
+            Some(Capability::FsWrite),
+            native::write_file,
+        );
+        vm.define_native("getEnv", 1, Some(Capability::Env), native::get_env);
+        vm.define_native("readLine", 0, None, native::read_line);
+        native::string::define(&mut vm);
+        native::math::define(&mut vm);
+        native::fiber::define(&mut vm);
 
         vm
     }
 
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn set_permissions(&mut self, permissions: Permissions) {
+        self.permissions = permissions;
+    }
+
+    pub fn set_stdout<W: Write + 'static>(&mut self, stdout: W) {
+        self.stdout = Box::new(stdout);
+    }
+
+    pub fn set_stderr<W: Write + 'static>(&mut self, stderr: W) {
+        self.stderr = Box::new(stderr);
+    }
+
+    pub fn set_stdin<R: BufRead + 'static>(&mut self, stdin: R) {
+        self.stdin = Box::new(stdin);
+    }
+
+    pub fn stdin(&mut self) -> &mut dyn BufRead {
+        &mut *self.stdin
+    }
+
+    /// Writes an error returned by `interpret` to the VM's stderr.
+    pub fn report(&mut self, error: &InterpretError) {
+        let written = match error {
+            InterpretError::Compile(diagnostics) => diagnostics
+                .iter()
+                .try_for_each(|diagnostic| writeln!(self.stderr, "{}", diagnostic)),
+            InterpretError::Runtime(error) => writeln!(self.stderr, "{}", error),
+            InterpretError::Internal(message) => {
+                writeln!(self.stderr, "Fatal error: {}", message)
+            }
+        };
+        written.ok();
+    }
+
+    pub fn rng(&mut self) -> &mut native::math::Rng {
+        &mut self.rng
+    }
+
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.globals.insert(Rc::from(name), value);
+    }
+
+    pub fn define_native(
+        &mut self,
+        name: &'static str,
+        arity: usize,
+        capability: Option<Capability>,
+        function: native::NativeFn,
+    ) {
+        let native = native::Function {
+            name,
+            arity,
+            capability,
+            function,
+        };
+        self.globals.insert(Rc::from(name), Value::Native(native));
+    }
+
+    pub fn interpret(&mut self, source: &str) -> Result<()> {
+        let tokens = scanner::scan_tokens(source);
+        if tokens.is_empty() {
+            return Ok(());
+        }
+
+        self.instruction_count = 0;
+        self.heap_size = 0;
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+
+        let function = compile(tokens).map_err(InterpretError::Compile)?;
+        let closure = Closure::new(function);
+        self.push(Value::Closure(closure.clone()))?;
+        self.call(closure, 0).ok();
+        self.run()
+    }
+
     fn reset_stack(&mut self) {
+        self.close_upvalues(&self.stack[0]);
+        for value in self.stack[0..self.stack_count].iter_mut() {
+            mem::take(value);
+        }
         self.stack_count = 0;
         for frame in self.frames.iter_mut() {
             frame.reset();
@@ -101,6 +287,47 @@ impl VM {
         self.frame_count = 0;
     }
 
+    /// Trades the running stack, frames and open upvalues for the fiber's.
+    fn swap_state(&mut self, fiber: &mut Fiber) {
+        mem::swap(&mut self.stack, &mut fiber.stack);
+        mem::swap(&mut self.stack_count, &mut fiber.stack_count);
+        mem::swap(&mut self.frames, &mut fiber.frames);
+        mem::swap(&mut self.frame_count, &mut fiber.frame_count);
+        mem::swap(&mut self.open_upvalues, &mut fiber.open_upvalues);
+    }
+
+    /// Switches to the fiber. Called from the `resume` native, whose result
+    /// then lands on the fiber's stack: in the slot of the `yield` it is
+    /// suspended at, or in its function's argument slot when it first starts.
+    pub(crate) fn resume(&mut self, fiber: &Rc<RefCell<Fiber>>, value: Value) -> NativeResult {
+        let mut state = fiber.borrow_mut();
+        match state.status {
+            FiberStatus::Suspended => {}
+            FiberStatus::Running => return Err("Can't resume a running fiber.".to_string()),
+            FiberStatus::Done => return Err("Can't resume a finished fiber.".to_string()),
+        }
+
+        state.status = FiberStatus::Running;
+        self.swap_state(&mut state);
+        drop(state);
+        self.fibers.push(Rc::clone(fiber));
+        Ok(value)
+    }
+
+    /// Switches back to whoever resumed the innermost running fiber, whose
+    /// `resume` call evaluates to `value`.
+    fn leave_fiber(&mut self, status: FiberStatus, value: Value) -> Result<()> {
+        let fiber = self
+            .fibers
+            .pop()
+            .ok_or(InterpretError::Internal("No fiber to leave."))?;
+        let mut fiber = fiber.borrow_mut();
+        fiber.status = status;
+        self.swap_state(&mut fiber);
+        self.stack[self.stack_count - 1] = value;
+        Ok(())
+    }
+
     #[inline(always)]
     fn current_frame(&self) -> &CallFrame {
         &self.frames[self.frame_count - 1]
@@ -122,31 +349,89 @@ impl VM {
             .chunk
     }
 
-    fn runtime_error<'a>(&mut self, string: &'a str) -> Result<()> {
-        eprintln!("{}", string);
+    /// Errors in a fiber end it and every fiber that resumed it, so the trace
+    /// runs through all of them down to the script.
+    fn runtime_error<T>(&mut self, kind: RuntimeErrorKind, message: &str) -> Result<T> {
+        let mut frames = trace(&self.frames[0..self.frame_count]);
+        for fiber in self.fibers.iter().rev() {
+            let fiber = fiber.borrow();
+            frames.extend(trace(&fiber.frames[0..fiber.frame_count]));
+        }
 
-        for frame in self.frames[0..self.frame_count].iter().rev() {
-            let function = &frame.closure.as_ref().unwrap().function;
-            let line = function.chunk.lines[frame.ip - 1];
+        while let Some(fiber) = self.fibers.pop() {
+            self.reset_stack();
+            let mut fiber = fiber.borrow_mut();
+            fiber.status = FiberStatus::Done;
+            self.swap_state(&mut fiber);
+        }
+        self.reset_stack();
+        Err(InterpretError::Runtime(RuntimeError {
+            kind,
+            message: message.to_string(),
+            frames,
+        }))
+    }
+
+    fn limit_exceeded<T>(&mut self, limit: Limit) -> Result<T> {
+        self.runtime_error(RuntimeErrorKind::Limit(limit), &limit.to_string())
+    }
+
+    #[inline(always)]
+    fn tick(&mut self) -> Result<()> {
+        self.instruction_count += 1;
 
-            eprint!("[line {}] in ", line);
-            match function.get_name() {
-                "<script>" => eprintln!("script"),
-                name => eprintln!("{}()", name),
+        if let Some(max) = self.limits.max_instructions {
+            if self.instruction_count > max {
+                return self.limit_exceeded(Limit::Instructions);
             }
         }
-        self.reset_stack();
-        Err(InterpretError::RuntimeError)
+
+        if let Some(deadline) = self.deadline {
+            if self
+                .instruction_count
+                .is_multiple_of(DEADLINE_CHECK_INTERVAL)
+                && Instant::now() >= deadline
+            {
+                return self.limit_exceeded(Limit::Timeout);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn allocate(&mut self, bytes: usize) -> Result<()> {
+        self.heap_size += bytes;
+        self.check_heap()
+    }
+
+    /// Every bigint result is a new allocation; the other numbers live inline.
+    #[inline(always)]
+    fn allocate_number(&mut self, value: &Value) -> Result<()> {
+        match value {
+            Value::BigInt(value) => self.allocate(value.size()),
+            _ => Ok(()),
+        }
     }
 
-    fn define_native(&mut self, name: &'static str, function: native::Function) {
-        self.globals.insert(name, Value::Native(function));
+    /// Counts memory a native allocated. Natives can only fail with a message,
+    /// so the heap limit is checked once the native returns.
+    pub(crate) fn charge(&mut self, bytes: usize) {
+        self.heap_size += bytes;
+    }
+
+    #[inline(always)]
+    fn check_heap(&mut self) -> Result<()> {
+        match self.limits.max_heap {
+            Some(max) if self.heap_size > max => self.limit_exceeded(Limit::Heap),
+            _ => Ok(()),
+        }
     }
 
     #[inline(always)]
     fn push(&mut self, value: Value) -> Result<()> {
         if self.stack_count == STACK_MAX {
-            return self.runtime_error("Stack overflow.");
+            return self.runtime_error(RuntimeErrorKind::StackOverflow, "Stack overflow.");
         }
         self.stack[self.stack_count] = value;
         self.stack_count += 1;
@@ -156,53 +441,148 @@ impl VM {
     #[inline(always)]
     fn pop(&mut self) -> Result<Value> {
         if self.stack_count == 0 {
-            return Err(InterpretError::InternalError("Can't pop on empty stack."));
+            return Err(InterpretError::Internal("Can't pop on empty stack."));
         }
 
         self.stack_count -= 1;
-        Ok(std::mem::take(&mut self.stack[self.stack_count]))
+        Ok(mem::take(&mut self.stack[self.stack_count]))
     }
 
     #[inline(always)]
     fn peek(&self, index: usize) -> Result<&Value> {
         self.stack
             .get(self.stack_count - 1 - index)
-            .ok_or(InterpretError::InternalError("Can't peek on empty stack."))
+            .ok_or(InterpretError::Internal("Can't peek on empty stack."))
     }
 
+    /// Checks the argument count against the function's parameters, fills in
+    /// nils for missing optional ones and gathers a rest parameter's list.
+    /// Returns the slot holding the callee.
     #[inline(always)]
-    fn call(&mut self, closure: Closure, arg_count: usize) -> Result<()> {
-        if arg_count != closure.function.arity {
+    fn bind_args(&mut self, function: &Function, arg_count: usize) -> Result<usize> {
+        let max = function.arity + function.optional;
+        if arg_count < function.arity || (arg_count > max && !function.variadic) {
             return self.runtime_error(
+                RuntimeErrorKind::Arity,
                 format!(
                     "Expected {} arguments but got {}.",
-                    closure.function.arity, arg_count
+                    function.expected_arity(),
+                    arg_count
                 )
                 .as_str(),
             );
         }
 
         let starts_at = self.stack_count - arg_count - 1;
+        for _ in arg_count..max {
+            self.push(Value::Nil)?;
+        }
+        if function.variadic {
+            let rest_at = starts_at + 1 + max;
+            let rest: Vec<Value> = self.stack[rest_at..self.stack_count]
+                .iter_mut()
+                .map(mem::take)
+                .collect();
+            self.stack_count = rest_at;
+            self.allocate(rest.len() * mem::size_of::<Value>())?;
+            self.push(Value::List(Rc::new(RefCell::new(rest))))?;
+        }
+
+        Ok(starts_at)
+    }
+
+    #[inline(always)]
+    fn call(&mut self, closure: Closure, arg_count: usize) -> Result<()> {
+        let starts_at = self.bind_args(&closure.function, arg_count)?;
+        if self.frame_count + 1 == CALL_FRAME_MAX {
+            return self.runtime_error(RuntimeErrorKind::StackOverflow, "Stack overflow.");
+        }
+
         let frame = &mut self.frames[self.frame_count];
         frame.starts_at = starts_at;
         frame.closure = Some(closure);
         frame.ip = 0;
+        frame.tail_calls = 0;
         self.frame_count += 1;
+        Ok(())
+    }
+
+    /// Calls a closure in place of the current function: its upvalues are
+    /// closed, the callee and arguments move down into the current frame's
+    /// window and the frame starts over with the new closure. Other callees
+    /// are called normally and the `Return` after the tail call returns
+    /// their result.
+    #[inline(always)]
+    fn tail_call(&mut self, arg_count: usize) -> Result<()> {
+        let closure = match self.peek(arg_count)?.clone() {
+            Value::Closure(closure) => closure,
+            callee => return self.call_value(callee, arg_count),
+        };
+
+        let from = self.bind_args(&closure.function, arg_count)?;
+        let starts_at = self.current_frame().starts_at;
+        self.close_upvalues(&self.stack[starts_at]);
 
-        if self.frame_count == CALL_FRAME_MAX {
-            return self.runtime_error("Stack overflow.");
+        let count = self.stack_count - from;
+        for slot in 0..count {
+            self.stack[starts_at + slot] = mem::take(&mut self.stack[from + slot]);
         }
+        for value in self.stack[(starts_at + count).min(from)..from].iter_mut() {
+            mem::take(value);
+        }
+        self.stack_count = starts_at + count;
 
+        let frame = self.current_frame_mut();
+        frame.closure = Some(closure);
+        frame.ip = 0;
+        frame.tail_calls += 1;
         Ok(())
     }
 
     #[inline(always)]
-    fn call_native(&mut self, function: native::Function, arg_count: usize) -> Result<()> {
-        let arg_start = self.stack_count - arg_count - 1;
-        let result = function(&self.stack[arg_start..]);
+    fn call_native(&mut self, native: native::Function, arg_count: usize) -> Result<()> {
+        if arg_count != native.arity {
+            return self.runtime_error(
+                RuntimeErrorKind::Arity,
+                format!("Expected {} arguments but got {}.", native.arity, arg_count).as_str(),
+            );
+        }
+
+        let args: Vec<Value> = self.stack[self.stack_count - arg_count..self.stack_count]
+            .iter_mut()
+            .map(mem::take)
+            .collect();
         self.stack_count -= arg_count;
-        self.stack[self.stack_count - 1] = result;
-        Ok(())
+
+        if let Err(message) = self.permissions.check(&native, &args) {
+            return self.runtime_error(RuntimeErrorKind::Permission, message.as_str());
+        }
+
+        match (native.function)(self, &args) {
+            Ok(value) => {
+                self.check_heap()?;
+                self.stack[self.stack_count - 1] = value;
+                Ok(())
+            }
+            Err(message) => self.runtime_error(RuntimeErrorKind::Native, message.as_str()),
+        }
+    }
+
+    /// Replaces the list on top of the stack with its elements.
+    #[inline(always)]
+    fn spread_args(&mut self) -> Result<usize> {
+        let args = match self.pop()? {
+            Value::List(list) => list,
+            _ => return Err(InterpretError::Internal("Expected list of arguments.")),
+        };
+        let args = Rc::try_unwrap(args)
+            .map_err(|_| InterpretError::Internal("Argument list is shared."))?
+            .into_inner();
+        let arg_count = args.len();
+        for arg in args {
+            self.push(arg)?;
+        }
+        Ok(arg_count)
     }
 
     #[inline(always)]
@@ -210,7 +590,10 @@ impl VM {
         match callee {
             Value::Closure(closure) => self.call(closure, arg_count),
             Value::Native(function) => self.call_native(function, arg_count),
-            _ => self.runtime_error("Can only call functions and classes."),
+            _ => self.runtime_error(
+                RuntimeErrorKind::NotCallable,
+                "Can only call functions and classes.",
+            ),
         }
     }
 
@@ -222,7 +605,7 @@ impl VM {
         while current.is_some()
             && current.as_ref().unwrap().borrow().location as usize > location as usize
         {
-            previous = Some(Rc::clone(&current.as_ref().unwrap()));
+            previous = Some(Rc::clone(current.as_ref().unwrap()));
             _temp = if let Some(value) = &previous.as_ref().unwrap().borrow().next {
                 Some(Rc::clone(value))
             } else {
@@ -233,20 +616,19 @@ impl VM {
 
         if let Some(value) = current {
             let upvalue = value.borrow();
-            if upvalue.location as usize == location as usize {
-                return Rc::clone(&value);
+            if std::ptr::eq(upvalue.location, location) {
+                return Rc::clone(value);
             }
         }
 
         let created_upvalue = Rc::new(RefCell::new(Upvalue::new(
             location,
-            current.as_ref().map(|value| Rc::clone(&value)),
+            current.as_ref().map(Rc::clone),
         )));
 
-        if previous.is_none() {
-            self.open_upvalues = Some(Rc::clone(&created_upvalue));
-        } else {
-            previous.as_mut().unwrap().borrow_mut().next = Some(Rc::clone(&created_upvalue));
+        match previous {
+            Some(previous) => previous.borrow_mut().next = Some(Rc::clone(&created_upvalue)),
+            None => self.open_upvalues = Some(Rc::clone(&created_upvalue)),
         }
 
         created_upvalue
@@ -259,7 +641,7 @@ impl VM {
         {
             let rc = self.open_upvalues.as_ref().unwrap();
             let mut upvalue = rc.borrow_mut();
-            let next = upvalue.next.as_ref().map(|value| Rc::clone(&value));
+            let next = upvalue.next.as_ref().map(Rc::clone);
             upvalue.close();
             drop(upvalue);
             self.open_upvalues = next;
@@ -275,8 +657,8 @@ impl VM {
         chunk
             .code
             .get(ip)
-            .map(|value| *value)
-            .ok_or(InterpretError::InternalError("Failed to read byte."))
+            .copied()
+            .ok_or(InterpretError::Internal("Failed to read byte."))
     }
 
     #[inline(always)]
@@ -285,7 +667,7 @@ impl VM {
         self.current_chunk()
             .constants
             .get(constant)
-            .ok_or(InterpretError::InternalError("Failed to read constant."))
+            .ok_or(InterpretError::Internal("Failed to read constant."))
     }
 
     #[inline(always)]
@@ -296,24 +678,49 @@ impl VM {
     }
 
     #[inline(always)]
-    fn read_string(&mut self) -> Result<&string::Handle> {
+    fn read_string(&mut self) -> Result<Rc<str>> {
         match self.read_constant()? {
-            Value::String(handle) => Ok(handle),
-            _ => Err(InterpretError::InternalError("Value was not a string.")),
+            Value::String(string) => Ok(Rc::clone(string)),
+            _ => Err(InterpretError::Internal("Value was not a string.")),
         }
     }
 
     fn run(&mut self) -> Result<()> {
         macro_rules! binary_op {
-            ($op: tt, $variant: ident) => {{
-                let value = match (self.pop()?, self.pop()?) {
-                (Value::Number(b), Value::Number(a)) => (a $op b),
-                _ => {
-                    return self.runtime_error("Operands must be numbers.");
+            ($function: path) => {{
+                let b = self.pop()?;
+                let a = self.pop()?;
+                match $function(&a, &b) {
+                    Ok(value) => {
+                        self.allocate_number(&value)?;
+                        self.push(value)?
+                    }
+                    Err(error) => return self.runtime_error(error.kind, error.message),
                 }
-                };
+            }};
+        }
 
-                self.push(Value::$variant(value))?
+        macro_rules! unary_op {
+            ($function: path) => {{
+                let a = self.pop()?;
+                match $function(&a) {
+                    Ok(value) => {
+                        self.allocate_number(&value)?;
+                        self.push(value)?
+                    }
+                    Err(error) => return self.runtime_error(error.kind, error.message),
+                }
+            }};
+        }
+
+        macro_rules! comparison_op {
+            ($ordering: pat) => {{
+                let b = self.pop()?;
+                let a = self.pop()?;
+                match number::compare(&a, &b) {
+                    Ok(ordering) => self.push(Value::Bool(matches!(ordering, Some($ordering))))?,
+                    Err(error) => return self.runtime_error(error.kind, error.message),
+                }
             }};
         }
 
@@ -322,21 +729,18 @@ impl VM {
                 #![cfg(feature = "trace-execution")]
                 print!("          ");
                 for i in 0..self.stack_count {
-                    print!("[ ");
-                    self.stack[i].print();
-                    print!(" ]");
+                    print!("[ {} ]", self.stack[i]);
                 }
-                println!("");
+                println!();
                 let ip = self.current_frame().ip;
                 self.current_chunk().disassemble_instruction(ip);
             }
 
+            self.tick()?;
+
             let instruction = match self.read_u8()?.try_into() {
                 Ok(op) => op,
-                Err(value) => {
-                    let message = format!("Got unexpected instruction: '{}'", value);
-                    return self.runtime_error(message.as_str());
-                }
+                Err(_) => return Err(InterpretError::Internal("Got unexpected instruction.")),
             };
 
             match instruction {
@@ -361,30 +765,50 @@ impl VM {
                     self.stack[slot + offset] = self.peek(0)?.clone();
                 }
                 Op::GetGlobal => {
-                    let name = self.read_string()?.as_str().string;
-                    match self.globals.get(name) {
+                    let name = self.read_string()?;
+                    match self.globals.get(&name) {
                         Some(value) => {
                             let clone = value.clone();
                             self.push(clone)?
                         }
                         _ => {
                             let error = format!("Undefined variable '{}'.", name);
-                            return self.runtime_error(error.as_str());
+                            return self.runtime_error(
+                                RuntimeErrorKind::UndefinedVariable,
+                                error.as_str(),
+                            );
                         }
                     }
                 }
                 Op::DefineGlobal => {
-                    let name = self.read_string()?.as_str().string;
+                    let name = self.read_string()?;
                     let value = self.pop()?;
+                    self.constants.remove(&name);
+                    self.globals.insert(name, value);
+                }
+                Op::DefineConstant => {
+                    let name = self.read_string()?;
+                    let value = self.pop()?;
+                    self.constants.insert(Rc::clone(&name));
                     self.globals.insert(name, value);
                 }
                 Op::SetGlobal => {
                     let name = self.read_string()?;
-                    let string = name.as_str().string;
-                    if self.globals.insert(string, self.peek(0)?.clone()).is_none() {
-                        self.globals.remove(string);
-                        let error = format!("Undefined variable '{}'.", string);
-                        return self.runtime_error(error.as_str());
+                    if self.constants.contains(&name) {
+                        let error = format!("Can't assign to constant '{}'.", name);
+                        return self
+                            .runtime_error(RuntimeErrorKind::ConstantAssignment, error.as_str());
+                    }
+                    let value = self.peek(0)?.clone();
+                    match self.globals.get_mut(&name) {
+                        Some(global) => *global = value,
+                        None => {
+                            let error = format!("Undefined variable '{}'.", name);
+                            return self.runtime_error(
+                                RuntimeErrorKind::UndefinedVariable,
+                                error.as_str(),
+                            );
+                        }
                     }
                 }
                 Op::GetUpvalue => {
@@ -408,42 +832,65 @@ impl VM {
                     let a = self.pop()?;
                     self.push(Value::Bool(a == b))?
                 }
-                Op::Greater => binary_op!(>, Bool),
-                Op::Less => binary_op!(<, Bool),
+                Op::Greater => comparison_op!(Ordering::Greater),
+                Op::Less => comparison_op!(Ordering::Less),
                 Op::Add => {
-                    let value = match (self.pop()?, self.pop()?) {
-                        (Value::Number(b), Value::Number(a)) => Value::Number(a + b),
-                        (Value::String(b), Value::String(a)) => Value::String(a + b),
-                        _ => {
-                            return self
-                                .runtime_error("Operands must be two numbers or two strings.");
+                    let b = self.pop()?;
+                    let a = self.pop()?;
+                    let value = match (&a, &b) {
+                        (Value::String(a), Value::String(b)) => {
+                            self.allocate(a.len() + b.len())?;
+                            Value::String(Rc::from([&**a, &**b].concat()))
                         }
+                        _ => match number::add(&a, &b) {
+                            Ok(value) => {
+                                self.allocate_number(&value)?;
+                                value
+                            }
+                            Err(error) => return self.runtime_error(error.kind, error.message),
+                        },
                     };
 
                     self.push(value)?
                 }
-                Op::Subtract => binary_op!(-, Number),
-                Op::Multiply => binary_op!(*, Number),
-                Op::Divide => binary_op!(/, Number),
+                Op::Subtract => binary_op!(number::subtract),
+                Op::Multiply => binary_op!(number::multiply),
+                Op::Divide => binary_op!(number::divide),
+                Op::Modulo => binary_op!(number::modulo),
+                Op::Power => binary_op!(number::power),
+                Op::IntDivide => binary_op!(number::int_divide),
+                Op::BitAnd => binary_op!(number::bit_and),
+                Op::BitOr => binary_op!(number::bit_or),
+                Op::BitXor => binary_op!(number::bit_xor),
+                Op::ShiftLeft => binary_op!(number::shift_left),
+                Op::ShiftRight => binary_op!(number::shift_right),
+                Op::BitNot => unary_op!(number::bit_not),
                 Op::Not => {
                     let value = self.pop()?.is_falsy();
                     self.push(Value::Bool(value))?
                 }
-                Op::Negate => {
-                    let num = match self.pop()? {
-                        Value::Number(num) => num,
-                        _ => {
-                            return self.runtime_error("Operand must be a number.");
-                        }
-                    };
-                    self.push(Value::Number(-num))?
-                }
+                Op::Negate => unary_op!(number::negate),
                 Op::Print => {
-                    self.pop()?.println();
+                    let value = self.pop()?;
+                    writeln!(self.stdout, "{}", value)
+                        .map_err(|_| InterpretError::Internal("Failed to write to stdout."))?;
+                }
+                Op::BuildString => {
+                    let count = self.read_u8()? as usize;
+                    let string: String = self.stack[self.stack_count - count..self.stack_count]
+                        .iter()
+                        .map(|value| value.to_string())
+                        .collect();
+                    self.stack_count -= count;
+                    for value in self.stack[self.stack_count..self.stack_count + count].iter_mut() {
+                        mem::take(value);
+                    }
+                    self.allocate(string.len())?;
+                    self.push(Value::String(Rc::from(string)))?
                 }
                 Op::Jump => {
                     let offset: usize = self.read_u16()?.into();
-                    let mut frame = self.current_frame_mut();
+                    let frame = self.current_frame_mut();
                     frame.ip += offset;
                 }
                 Op::JumpIfFalse => {
@@ -453,6 +900,126 @@ impl VM {
                         frame.ip += offset
                     }
                 }
+                Op::JumpIfNotNil => {
+                    let offset: usize = self.read_u16()?.into();
+                    if !matches!(self.peek(0)?, Value::Nil) {
+                        let frame = self.current_frame_mut();
+                        frame.ip += offset
+                    }
+                }
+                Op::JumpTable => {
+                    let low = match self.read_constant()? {
+                        Value::Int(low) => *low,
+                        _ => return Err(InterpretError::Internal("Expected int for jump table")),
+                    };
+                    let count: usize = self.read_u16()?.into();
+                    let table = self.current_frame().ip;
+                    // Anything that isn't a whole number inside the table
+                    // takes the default slot, which sits after the cases.
+                    let case = match self.pop()? {
+                        Value::Int(value) => Some(value),
+                        Value::Number(value) if value.fract() == 0.0 => {
+                            BigInt::from_f64(value).as_ref().and_then(BigInt::to_i64)
+                        }
+                        Value::BigInt(value) => value.to_i64(),
+                        _ => None,
+                    }
+                    .and_then(|value| value.checked_sub(low))
+                    .and_then(|index| usize::try_from(index).ok())
+                    .filter(|index| *index < count)
+                    .unwrap_or(count);
+
+                    let code = &self.current_chunk().code;
+                    let at = table + case * 2;
+                    let offset = u16::from_be_bytes([code[at], code[at + 1]]) as usize;
+                    self.current_frame_mut().ip = table + (count + 1) * 2 + offset;
+                }
+                Op::InRange => {
+                    let inclusive = self.read_u8()? == 1;
+                    let end = self.pop()?;
+                    let start = self.pop()?;
+                    let value = self.pop()?;
+                    // Ranges only hold numbers, so anything else simply
+                    // doesn't match instead of being a type error.
+                    let ordering = |a: &Value, b: &Value| number::compare(a, b).ok().flatten();
+                    let above_start = matches!(
+                        ordering(&value, &start),
+                        Some(Ordering::Greater | Ordering::Equal)
+                    );
+                    let below_end = match ordering(&value, &end) {
+                        Some(Ordering::Less) => true,
+                        Some(Ordering::Equal) => inclusive,
+                        _ => false,
+                    };
+                    self.push(Value::Bool(above_start && below_end))?
+                }
+                Op::IterNext => {
+                    let slot: usize = self.read_u8()?.into();
+                    let offset: usize = self.read_u16()?.into();
+                    let iterable = self.current_frame().starts_at + slot;
+                    // Closures from the previous iteration keep the value they
+                    // captured, so every iteration gets a fresh variable.
+                    self.close_upvalues(&self.stack[iterable + 2]);
+                    let index = match self.stack[iterable + 1] {
+                        Value::Int(index) => index as usize,
+                        _ => {
+                            return Err(InterpretError::Internal("Expected int for iterator index"))
+                        }
+                    };
+
+                    let next = match &self.stack[iterable] {
+                        Value::List(list) => Some(
+                            list.borrow()
+                                .get(index)
+                                .cloned()
+                                .map(|value| (value, index + 1)),
+                        ),
+                        // Strings yield characters, and the index is a byte
+                        // offset so each step is constant time.
+                        Value::String(string) => Some(string[index..].chars().next().map(|c| {
+                            let value = Value::String(Rc::from(c.to_string()));
+                            (value, index + c.len_utf8())
+                        })),
+                        Value::Closure(_) | Value::Native(_) => None,
+                        _ => {
+                            return self.runtime_error(
+                                RuntimeErrorKind::Type,
+                                "Can only iterate over lists, strings and functions.",
+                            )
+                        }
+                    };
+
+                    match next {
+                        Some(Some((value, index))) => {
+                            if let Value::String(string) = &value {
+                                self.allocate(string.len())?;
+                            }
+                            self.stack[iterable + 1] = Value::Int(index as i64);
+                            self.push(value)?
+                        }
+                        Some(None) => self.current_frame_mut().ip += offset,
+                        // An iterator function is called for each value, and
+                        // `IterCheck` looks at what it returned.
+                        None => {
+                            let callee = self.stack[iterable].clone();
+                            self.push(callee.clone())?;
+                            self.call_value(callee, 0)?;
+                        }
+                    }
+                }
+                Op::IterCheck => {
+                    let slot: usize = self.read_u8()?.into();
+                    let offset: usize = self.read_u16()?.into();
+                    let iterable = self.current_frame().starts_at + slot;
+                    let callable =
+                        matches!(self.stack[iterable], Value::Closure(_) | Value::Native(_));
+                    // Lists and strings can hold nil, but for an iterator
+                    // function it always means it's done.
+                    if callable && matches!(self.peek(0)?, Value::Nil) {
+                        self.pop()?;
+                        self.current_frame_mut().ip += offset;
+                    }
+                }
                 Op::Loop => {
                     let offset = self.read_u16()?;
                     let frame = self.current_frame_mut();
@@ -463,14 +1030,54 @@ impl VM {
                     let callee = self.peek(arg_count)?.clone();
                     self.call_value(callee, arg_count)?;
                 }
+                Op::Collect => {
+                    let count = self.read_u8()? as usize;
+                    let values: Vec<Value> = self.stack[self.stack_count - count..self.stack_count]
+                        .iter_mut()
+                        .map(mem::take)
+                        .collect();
+                    self.stack_count -= count;
+                    self.allocate(count * mem::size_of::<Value>())?;
+                    self.push(Value::List(Rc::new(RefCell::new(values))))?
+                }
+                Op::Extend => {
+                    let values = match self.pop()? {
+                        Value::List(list) => list,
+                        _ => {
+                            return self
+                                .runtime_error(RuntimeErrorKind::Type, "Can only spread lists.")
+                        }
+                    };
+                    let values = values.borrow().clone();
+                    self.allocate(values.len() * mem::size_of::<Value>())?;
+                    match self.peek(0)? {
+                        Value::List(list) => list.borrow_mut().extend(values),
+                        _ => return Err(InterpretError::Internal("Expected list to extend.")),
+                    }
+                }
+                Op::TailCall => {
+                    let arg_count = self.read_u8()? as usize;
+                    self.tail_call(arg_count)?;
+                }
+                Op::CallSpread => {
+                    let arg_count = self.spread_args()?;
+                    let callee = self.peek(arg_count)?.clone();
+                    self.call_value(callee, arg_count)?;
+                }
+                Op::TailCallSpread => {
+                    let arg_count = self.spread_args()?;
+                    self.tail_call(arg_count)?;
+                }
                 Op::Closure => {
                     let fun = match self.read_constant()? {
                         Value::Function(fun) => Ok(fun.clone()),
-                        _ => Err(InterpretError::InternalError(
-                            "Expected function for closure",
-                        )),
+                        _ => Err(InterpretError::Internal("Expected function for closure")),
                     }?;
                     let upvalue_count = fun.upvalue_count;
+                    self.allocate(
+                        mem::size_of::<Closure>()
+                            + upvalue_count * mem::size_of::<Rc<RefCell<Upvalue>>>(),
+                    )?;
                     let mut closure = Closure::new(fun);
                     let offset = self.current_frame().starts_at;
                     for i in 0..upvalue_count {
@@ -478,6 +1085,7 @@ impl VM {
                         let index = self.read_u8()? as usize;
                         let upvalue = if is_local == 1 {
                             let value: *mut Value = &mut self.stack[offset + index];
+                            self.allocate(mem::size_of::<Upvalue>())?;
                             self.capture_upvalue(value)
                         } else {
                             self.current_frame().closure.as_ref().unwrap().upvalues[i].clone()
@@ -490,14 +1098,34 @@ impl VM {
                     self.close_upvalues(&self.stack[self.stack_count - 1]);
                     self.pop()?;
                 }
+                Op::Yield => {
+                    let value = self.pop()?;
+                    if self.fibers.is_empty() {
+                        return self.runtime_error(
+                            RuntimeErrorKind::Fiber,
+                            "Can't yield outside of a fiber.",
+                        );
+                    }
+                    self.push(Value::Nil)?;
+                    self.leave_fiber(FiberStatus::Suspended, value)?;
+                }
                 Op::Return => {
                     let result = self.pop()?;
                     let starts_at = self.current_frame().starts_at;
                     self.close_upvalues(&self.stack[starts_at]);
                     self.frame_count -= 1;
                     if self.frame_count == 0 {
-                        self.pop()?;
-                        return Ok(());
+                        if self.fibers.is_empty() {
+                            self.pop()?;
+                            return Ok(());
+                        }
+
+                        for value in self.stack[starts_at..self.stack_count].iter_mut() {
+                            mem::take(value);
+                        }
+                        self.stack_count = starts_at;
+                        self.leave_fiber(FiberStatus::Done, result)?;
+                        continue;
                     }
 
                     self.stack_count = starts_at;
@@ -507,3 +1135,19 @@ impl VM {
         }
     }
 }
+
+/// Innermost call first.
+fn trace(frames: &[CallFrame]) -> Vec<Frame> {
+    frames
+        .iter()
+        .rev()
+        .map(|frame| {
+            let function = &frame.closure.as_ref().unwrap().function;
+            Frame {
+                function: function.get_name().to_string(),
+                line: function.chunk.lines[frame.ip.saturating_sub(1)],
+                tail_calls: frame.tail_calls,
+            }
+        })
+        .collect()
+}
@@ -16,8 +16,9 @@ const parseTestFile = async (filename: string): Promise<FileExpectation> => {
   const errorAtRegExp = /\/\/ (Error at '.+)/;
 
   if (errorAtRegExp.test(file)) {
-    const [, error] = errorAtRegExp.exec(file)!;
-    return { code: 65, error };
+    const match = errorAtRegExp.exec(file)!;
+    const line = file.slice(0, match.index).split("\n").length;
+    return { code: 65, error: `[line ${line}] ${match[1]}` };
   }
 
   const parseErrorRegExp = /\/\/ \[/g;
@@ -0,0 +1,74 @@
+use rustlox::error::{Diagnostic, Frame, InterpretError, Location, RuntimeErrorKind};
+use rustlox::vm::VM;
+
+fn interpret(source: &str) -> Result<(), InterpretError> {
+    let mut vm = VM::new();
+    vm.set_stdout(Vec::new());
+    vm.interpret(source)
+}
+
+fn frame(function: &str, line: i32, tail_calls: usize) -> Frame {
+    Frame {
+        function: function.to_string(),
+        line,
+        tail_calls,
+    }
+}
+
+#[test]
+fn runtime_error() {
+    let source = "fun inner() {\n  return 1 + nil;\n}\nfun outer() {\n  inner();\n}\nouter();";
+    let Err(InterpretError::Runtime(error)) = interpret(source) else {
+        panic!("expected a runtime error");
+    };
+    assert_eq!(error.kind, RuntimeErrorKind::Type);
+    assert_eq!(
+        error.message,
+        "Operands must be two numbers or two strings."
+    );
+    assert_eq!(
+        error.frames,
+        vec![
+            frame("inner", 2, 0),
+            frame("outer", 5, 0),
+            frame("<script>", 7, 0)
+        ]
+    );
+}
+
+#[test]
+fn runtime_error_after_a_tail_call() {
+    let source =
+        "fun inner() {\n  return 1 + nil;\n}\nfun outer() {\n  return inner();\n}\nouter();";
+    let Err(InterpretError::Runtime(error)) = interpret(source) else {
+        panic!("expected a runtime error");
+    };
+    assert_eq!(
+        error.frames,
+        vec![frame("inner", 2, 1), frame("<script>", 7, 0)]
+    );
+}
+
+#[test]
+fn parse_error() {
+    assert_eq!(
+        interpret("var a = 1;\nprint ;"),
+        Err(InterpretError::Compile(vec![Diagnostic {
+            line: 2,
+            location: Location::Token(";".to_string()),
+            message: "Expected expression.".to_string(),
+        }]))
+    );
+}
+
+#[test]
+fn compile_error() {
+    assert_eq!(
+        interpret("{\n  var a;\n  var a;\n}"),
+        Err(InterpretError::Compile(vec![Diagnostic {
+            line: 3,
+            location: Location::Token("a".to_string()),
+            message: "Already variable with this name in this scope.".to_string(),
+        }]))
+    );
+}
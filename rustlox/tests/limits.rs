@@ -0,0 +1,138 @@
+use rustlox::error::{InterpretError, RuntimeErrorKind};
+use rustlox::limits::{Limit, Limits};
+use rustlox::vm::{Fiber, VM};
+use std::time::Duration;
+
+fn vm_with(limits: Limits) -> VM {
+    let mut vm = VM::new();
+    vm.set_stdout(Vec::new());
+    vm.set_limits(limits);
+    vm
+}
+
+fn error_kind(result: Result<(), InterpretError>) -> Option<RuntimeErrorKind> {
+    match result {
+        Err(InterpretError::Runtime(error)) => Some(error.kind),
+        _ => None,
+    }
+}
+
+fn max_instructions(count: u64) -> Limits {
+    Limits {
+        max_instructions: Some(count),
+        ..Limits::default()
+    }
+}
+
+#[test]
+fn instruction_limit() {
+    let mut vm = vm_with(max_instructions(1000));
+    assert_eq!(
+        error_kind(vm.interpret("while (true) {}")),
+        Some(RuntimeErrorKind::Limit(Limit::Instructions))
+    );
+}
+
+#[test]
+fn instruction_limit_not_reached() {
+    let mut vm = vm_with(max_instructions(1000));
+    assert_eq!(vm.interpret("var a = 1 + 2;"), Ok(()));
+}
+
+#[test]
+fn instruction_limit_on_a_fresh_frame() {
+    let mut vm = vm_with(max_instructions(4));
+    assert_eq!(
+        error_kind(vm.interpret("fun f() { return 1; } print f();")),
+        Some(RuntimeErrorKind::Limit(Limit::Instructions))
+    );
+}
+
+#[test]
+fn instruction_limit_after_a_tail_call() {
+    let mut vm = vm_with(max_instructions(105));
+    let source = "fun loop(n) { if (n == 0) return 0; return loop(n - 1); } print loop(1000000);";
+    assert_eq!(
+        error_kind(vm.interpret(source)),
+        Some(RuntimeErrorKind::Limit(Limit::Instructions))
+    );
+}
+
+#[test]
+fn timeout() {
+    let mut vm = vm_with(Limits {
+        timeout: Some(Duration::from_millis(50)),
+        ..Limits::default()
+    });
+    assert_eq!(
+        error_kind(vm.interpret("while (true) {}")),
+        Some(RuntimeErrorKind::Limit(Limit::Timeout))
+    );
+}
+
+#[test]
+fn heap_limit() {
+    let mut vm = vm_with(Limits {
+        max_heap: Some(10_000),
+        ..Limits::default()
+    });
+    assert_eq!(
+        error_kind(vm.interpret("var s = \"x\"; while (true) s = s + s;")),
+        Some(RuntimeErrorKind::Limit(Limit::Heap))
+    );
+}
+
+#[test]
+fn heap_limit_in_string_natives() {
+    let mut vm = vm_with(Limits {
+        max_heap: Some(10_000),
+        ..Limits::default()
+    });
+    assert_eq!(
+        error_kind(vm.interpret("var s = \"x\"; while (true) s = replace(s, \"x\", \"xx\");")),
+        Some(RuntimeErrorKind::Limit(Limit::Heap))
+    );
+}
+
+#[test]
+fn heap_limit_in_list_natives() {
+    let mut vm = vm_with(Limits {
+        max_heap: Some(10_000),
+        ..Limits::default()
+    });
+    assert_eq!(
+        error_kind(vm.interpret("while (true) split(\"a,b,c\", \",\");")),
+        Some(RuntimeErrorKind::Limit(Limit::Heap))
+    );
+}
+
+#[test]
+fn heap_limit_counts_bigints() {
+    let mut vm = vm_with(Limits {
+        max_heap: Some(10_000),
+        ..Limits::default()
+    });
+    assert_eq!(
+        error_kind(vm.interpret("var n = 3n; for (var i = 0; i < 22; i = i + 1) n = n * n;")),
+        Some(RuntimeErrorKind::Limit(Limit::Heap))
+    );
+}
+
+#[test]
+fn heap_limit_counts_fibers() {
+    let mut vm = vm_with(Limits {
+        max_heap: Some(Fiber::SIZE * 2),
+        ..Limits::default()
+    });
+    assert_eq!(
+        error_kind(vm.interpret("fun f() {} for (var i = 0; i < 3; i++) Fiber(f);")),
+        Some(RuntimeErrorKind::Limit(Limit::Heap))
+    );
+}
+
+#[test]
+fn vm_is_reusable_after_a_limit() {
+    let mut vm = vm_with(max_instructions(1000));
+    assert!(vm.interpret("fun f() { while (true) {} } f();").is_err());
+    assert_eq!(vm.interpret("var a = 1; print a;"), Ok(()));
+}
@@ -0,0 +1,85 @@
+use rustlox::error::{InterpretError, RuntimeErrorKind};
+use rustlox::permissions::{Capability, Permissions};
+use rustlox::vm::VM;
+use std::fs;
+use std::path::PathBuf;
+
+/// A fresh directory per test, so tests running in parallel don't collide.
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rustlox-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("data")).unwrap();
+    fs::write(dir.join("data/inside.txt"), "inside").unwrap();
+    fs::write(dir.join("outside.txt"), "outside").unwrap();
+    dir
+}
+
+fn vm_with(capability: Capability, path: &PathBuf) -> VM {
+    let mut permissions = Permissions::default();
+    permissions.grant_path(capability, path);
+    let mut vm = VM::new();
+    vm.set_permissions(permissions);
+    vm
+}
+
+fn read(vm: &mut VM, path: PathBuf) -> Result<(), InterpretError> {
+    vm.interpret(&format!("var contents = readFile(\"{}\");", path.display()))
+}
+
+fn error_kind(result: Result<(), InterpretError>) -> Option<RuntimeErrorKind> {
+    match result {
+        Err(InterpretError::Runtime(error)) => Some(error.kind),
+        _ => None,
+    }
+}
+
+#[test]
+fn read_inside_granted_directory() {
+    let dir = scratch("read-inside");
+    let mut vm = vm_with(Capability::FsRead, &dir.join("data"));
+    assert_eq!(read(&mut vm, dir.join("data/inside.txt")), Ok(()));
+}
+
+#[test]
+fn read_outside_granted_directory() {
+    let dir = scratch("read-outside");
+    let mut vm = vm_with(Capability::FsRead, &dir.join("data"));
+    assert_eq!(
+        error_kind(read(&mut vm, dir.join("data/../outside.txt"))),
+        Some(RuntimeErrorKind::Permission)
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn read_through_symlink_out_of_granted_directory() {
+    let dir = scratch("read-symlink");
+    std::os::unix::fs::symlink(dir.join("outside.txt"), dir.join("data/link")).unwrap();
+    let mut vm = vm_with(Capability::FsRead, &dir.join("data"));
+    assert_eq!(
+        error_kind(read(&mut vm, dir.join("data/link"))),
+        Some(RuntimeErrorKind::Permission)
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn write_through_symlinked_directory_out_of_granted_directory() {
+    let dir = scratch("write-symlink");
+    std::os::unix::fs::symlink(&dir, dir.join("data/up")).unwrap();
+    let mut vm = vm_with(Capability::FsWrite, &dir.join("data"));
+    let target = dir.join("data/up/new.txt");
+    let result = vm.interpret(&format!("writeFile(\"{}\", \"x\");", target.display()));
+    assert_eq!(error_kind(result), Some(RuntimeErrorKind::Permission));
+    assert!(!dir.join("new.txt").exists());
+}
+
+#[test]
+fn write_new_file_inside_granted_directory() {
+    let dir = scratch("write-inside");
+    let mut vm = vm_with(Capability::FsWrite, &dir.join("data"));
+    let target = dir.join("data/new.txt");
+    let result = vm.interpret(&format!("writeFile(\"{}\", \"x\");", target.display()));
+    assert_eq!(result, Ok(()));
+    assert_eq!(fs::read_to_string(target).unwrap(), "x");
+}
@@ -0,0 +1,75 @@
+use rustlox::vm::VM;
+use std::cell::RefCell;
+use std::io::{self, Cursor, Write};
+use std::rc::Rc;
+
+/// A `Vec<u8>` the test can still read after handing it to the VM.
+#[derive(Clone, Default)]
+struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+impl Buffer {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn print_writes_to_stdout() {
+    let stdout = Buffer::default();
+    let mut vm = VM::new();
+    vm.set_stdout(stdout.clone());
+
+    vm.interpret("print 1 + 2; print \"done\";").unwrap();
+    assert_eq!(stdout.contents(), "3\ndone\n");
+}
+
+#[test]
+fn read_line_reads_from_stdin() {
+    let stdout = Buffer::default();
+    let mut vm = VM::new();
+    vm.set_stdout(stdout.clone());
+    vm.set_stdin(Cursor::new("first\nsecond\n"));
+
+    vm.interpret("print readLine(); print readLine(); print readLine();")
+        .unwrap();
+    assert_eq!(stdout.contents(), "first\nsecond\nnil\n");
+}
+
+#[test]
+fn stdin_is_shared_with_the_host() {
+    let stdout = Buffer::default();
+    let mut vm = VM::new();
+    vm.set_stdout(stdout.clone());
+    vm.set_stdin(Cursor::new("script\nhost\n"));
+
+    vm.interpret("print readLine();").unwrap();
+    let mut line = String::new();
+    vm.stdin().read_line(&mut line).unwrap();
+    assert_eq!(stdout.contents(), "script\n");
+    assert_eq!(line, "host\n");
+}
+
+#[test]
+fn report_writes_to_stderr() {
+    let stderr = Buffer::default();
+    let mut vm = VM::new();
+    vm.set_stdout(Vec::new());
+    vm.set_stderr(stderr.clone());
+
+    let error = vm.interpret("print nil + 1;").unwrap_err();
+    vm.report(&error);
+    assert_eq!(
+        stderr.contents(),
+        "Operands must be two numbers or two strings.\n[line 1] in script\n"
+    );
+}